@@ -0,0 +1,27 @@
+use advent_of_code_2019::intcode::asm;
+use std::io::Read;
+use std::{env, fs, io, process};
+
+fn main() {
+	let path = env::args().nth(1);
+	let source = match &path {
+		Some(path) => fs::read_to_string(path).unwrap_or_else(|e| {
+			eprintln!("asm: {}: {}", path, e);
+			process::exit(1);
+		}),
+		None => {
+			let mut source = String::new();
+			io::stdin().read_to_string(&mut source).unwrap_or_else(|e| {
+				eprintln!("asm: {}", e);
+				process::exit(1);
+			});
+			source
+		}
+	};
+	let image = asm::assemble(&source).unwrap_or_else(|e| {
+		eprintln!("asm: {}:{}", path.as_deref().unwrap_or("<stdin>"), e);
+		process::exit(1);
+	});
+	let words: Vec<String> = image.iter().map(|w| w.to_string()).collect();
+	println!("{}", words.join(","));
+}
@@ -0,0 +1,371 @@
+//! Times the Intcode machine with and without its decoded-instruction cache,
+//! checking that both ways agree on every output, step count and final
+//! memory, and against the day 5 interpreter the machine replaced, checking
+//! that it prints the same outputs. The image the optimizer produces is run
+//! the same way and checked against the original: the same outputs in no
+//! more steps, leaving the same memory apart from the words it rewrote.
+//!
+//! It always runs a prime-counting program checked in below, and then the
+//! day 5 and day 7 inputs if they are there.
+
+use advent_of_code_2019::intcode::amplifier::permutations;
+use advent_of_code_2019::intcode::network::Network;
+use advent_of_code_2019::intcode::optimize::optimize;
+use advent_of_code_2019::intcode::{self, asm, io, Vm};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use std::{env, fs, process};
+
+const ROUNDS: u32 = 20;
+
+/// Counts the primes below its input by trial division, then prints the
+/// count and the largest of them. Each prime found is written into the
+/// operand of the final `OUT`, so the cache has code to invalidate.
+const PRIMES: &str = "
+	        IN [n]
+	outer:  LT [c], [n], [t]
+	        JF [t], #done
+	        ADD #2, #0, [d]
+	inner:  MUL [d], [d], [sq]
+	        LT [c], [sq], [t]
+	        JT [t], #prime
+	        MUL [d], #-1, [nd]
+	        ADD [c], #0, [r]
+	mod:    LT [r], [d], [t]
+	        JT [t], #check
+	        ADD [r], [nd], [r]
+	        JT #1, #mod
+	check:  JF [r], #next
+	        ADD [d], #1, [d]
+	        JT #1, #inner
+	prime:  ADD [count], #1, [count]
+	        ADD [c], #0, [last+1]
+	next:   ADD [c], #1, [c]
+	        JT #1, #outer
+	done:   OUT [count]
+	last:   OUT #0
+	        HLT
+	n:      DB 0
+	c:      DB 2
+	d:      DB 0
+	nd:     DB 0
+	r:      DB 0
+	sq:     DB 0
+	t:      DB 0
+	count:  DB 0
+";
+
+/// Everything observable about a finished run.
+#[derive(PartialEq, Eq, Debug)]
+struct Run {
+	outputs: Vec<i64>,
+	steps: u64,
+	memory: Vec<i64>,
+}
+
+fn machine(program: &[i64], cached: bool) -> Vm {
+	let mut vm = Vm::new(program.to_vec());
+	vm.set_decode_cache(cached);
+	vm
+}
+
+/// Runs the program once for each input value.
+fn each_input(program: &[i64], inputs: &[i64], cached: bool) -> Vec<Run> {
+	inputs
+		.iter()
+		.map(|&input| {
+			let mut vm = machine(program, cached);
+			let mut outputs = Vec::new();
+			vm.run_with(io::iter(Some(input)), &mut outputs).unwrap();
+			Run {
+				outputs,
+				steps: vm.steps(),
+				memory: vm.memory().to_vec(),
+			}
+		})
+		.collect()
+}
+
+fn day07(program: &[i64], cached: bool) -> Vec<Run> {
+	let mut runs = Vec::new();
+	for (alphabet, ring) in &[([0, 1, 2, 3, 4], false), ([5, 6, 7, 8, 9], true)]
+	{
+		for phases in permutations(alphabet, 5) {
+			let mut network = Network::new();
+			for (i, &phase) in phases.iter().enumerate() {
+				network.add(machine(program, cached));
+				network.send(i, phase);
+				if i < 4 || *ring {
+					network.connect(i, (i + 1) % 5);
+				}
+			}
+			network.send(0, 0);
+			for outcome in network.run_in_turn().machines {
+				outcome.result.unwrap();
+				runs.push(Run {
+					outputs: outcome.outputs,
+					steps: outcome.vm.steps(),
+					memory: outcome.vm.memory().to_vec(),
+				});
+			}
+		}
+	}
+	runs
+}
+
+/// The day 5 interpreter as it was before the shared machine, with words
+/// widened to `i64`. It decodes every instruction afresh on every step.
+mod baseline {
+	use std::convert::TryFrom;
+
+	const ADD_OP: i64 = 1;
+	const MULT_OP: i64 = 2;
+	const IN_OP: i64 = 3;
+	const OUT_OP: i64 = 4;
+	const JIT_OP: i64 = 5;
+	const JIF_OP: i64 = 6;
+	const LT_OP: i64 = 7;
+	const EQ_OP: i64 = 8;
+	const HALT_OP: i64 = 99;
+	const POSITION_MODE: i64 = 0;
+	const IMMEDIATE_MODE: i64 = 1;
+
+	enum Instruction {
+		Add(i64, i64, usize),
+		Multiply(i64, i64, usize),
+		Input(usize),
+		Output(i64),
+		JumpIfTrue(i64, usize),
+		JumpIfFalse(i64, usize),
+		LessThan(i64, i64, usize),
+		Equals(i64, i64, usize),
+		Halt,
+	}
+
+	enum Mode {
+		Position,
+		Immediate,
+	}
+
+	impl TryFrom<i64> for Mode {
+		type Error = &'static str;
+		fn try_from(value: i64) -> Result<Self, Self::Error> {
+			match value {
+				POSITION_MODE => Ok(Mode::Position),
+				IMMEDIATE_MODE => Ok(Mode::Immediate),
+				_ => Err("unsupported parameter mode"),
+			}
+		}
+	}
+
+	fn fetch_parameter(pointer: usize, memory: &[i64], mode: Mode) -> i64 {
+		match mode {
+			Mode::Position => memory[memory[pointer] as usize],
+			Mode::Immediate => memory[pointer],
+		}
+	}
+
+	fn decode_instruction(pointer: usize, memory: &[i64]) -> Instruction {
+		let opcode = memory[pointer];
+		let fetch = |offset: usize, divisor: i64| {
+			let mode = Mode::try_from((opcode / divisor) % 10).unwrap();
+			fetch_parameter(pointer + offset, memory, mode)
+		};
+		let (a, b) = (|| fetch(1, 100), || fetch(2, 1000));
+		let c = || memory[pointer + 3] as usize;
+		match opcode % 100 {
+			ADD_OP => Instruction::Add(a(), b(), c()),
+			MULT_OP => Instruction::Multiply(a(), b(), c()),
+			IN_OP => Instruction::Input(memory[pointer + 1] as usize),
+			OUT_OP => Instruction::Output(a()),
+			JIT_OP => Instruction::JumpIfTrue(a(), b() as usize),
+			JIF_OP => Instruction::JumpIfFalse(a(), b() as usize),
+			LT_OP => Instruction::LessThan(a(), b(), c()),
+			EQ_OP => Instruction::Equals(a(), b(), c()),
+			HALT_OP => Instruction::Halt,
+			_ => panic!("unsupported opcode"),
+		}
+	}
+
+	pub fn execute_program(memory: &mut [i64], input: i64) -> Vec<i64> {
+		let mut i: usize = 0;
+		let mut output = Vec::new();
+		loop {
+			match decode_instruction(i, memory) {
+				Instruction::Add(a, b, c) => {
+					memory[c] = a + b;
+					i += 4;
+				}
+				Instruction::Multiply(a, b, c) => {
+					memory[c] = a * b;
+					i += 4;
+				}
+				Instruction::Input(a) => {
+					memory[a] = input;
+					i += 2;
+				}
+				Instruction::Output(a) => {
+					output.push(a);
+					i += 2;
+				}
+				Instruction::JumpIfTrue(a, b) => {
+					i = if a != 0 { b } else { i + 3 }
+				}
+				Instruction::JumpIfFalse(a, b) => {
+					i = if a == 0 { b } else { i + 3 }
+				}
+				Instruction::LessThan(a, b, c) => {
+					memory[c] = if a < b { 1 } else { 0 };
+					i += 4;
+				}
+				Instruction::Equals(a, b, c) => {
+					memory[c] = if a == b { 1 } else { 0 };
+					i += 4;
+				}
+				Instruction::Halt => break,
+			}
+		}
+		output
+	}
+}
+
+/// Whether a run of the optimized image does what the original run did.
+fn agrees(
+	original: &Run,
+	optimized: &Run,
+	rewritten: &BTreeSet<usize>,
+) -> bool {
+	original.outputs == optimized.outputs
+		&& optimized.steps <= original.steps
+		&& original.memory.len() == optimized.memory.len()
+		&& (original.memory.iter().zip(&optimized.memory).enumerate())
+			.all(|(address, (a, b))| a == b || rewritten.contains(&address))
+}
+
+fn time<T>(f: impl Fn() -> T) -> (T, Duration) {
+	let start = Instant::now();
+	let mut result = f();
+	for _ in 1..ROUNDS {
+		result = f();
+	}
+	(result, start.elapsed() / ROUNDS)
+}
+
+fn speedup(from: Duration, to: Duration) -> f64 {
+	from.as_secs_f64() / to.as_secs_f64()
+}
+
+/// Times `f` on `program`, and `baseline` too if the old interpreter can run
+/// it.
+fn bench(
+	name: &str,
+	program: &[i64],
+	f: impl Fn(&[i64], bool) -> Vec<Run>,
+	baseline: Option<&[i64]>,
+) {
+	let (uncached, uncached_time) = time(|| f(program, false));
+	let (cached, cached_time) = time(|| f(program, true));
+	if uncached != cached {
+		eprintln!("bench: {}: cached and uncached runs differ", name);
+		process::exit(1);
+	}
+	println!(
+		"{}: {:?} uncached, {:?} cached ({:.2}x), {} runs agree",
+		name,
+		uncached_time,
+		cached_time,
+		speedup(uncached_time, cached_time),
+		cached.len()
+	);
+	if let Some(inputs) = baseline {
+		let (outputs, baseline_time) = time(|| {
+			let run = |&input| {
+				baseline::execute_program(&mut program.to_vec(), input)
+			};
+			inputs.iter().map(run).collect::<Vec<_>>()
+		});
+		if !outputs.iter().eq(cached.iter().map(|run| &run.outputs)) {
+			eprintln!("bench: {}: the old interpreter's outputs differ", name);
+			process::exit(1);
+		}
+		println!(
+			"{}: {:?} on the old day 5 interpreter ({:.2}x the speed of the \
+			 cached machine), outputs agree",
+			name,
+			baseline_time,
+			speedup(cached_time, baseline_time)
+		);
+	}
+
+	let optimized = match optimize(program) {
+		Ok(optimized) => optimized,
+		Err(refusal) => {
+			println!("{}: not optimized, as {}", name, refusal);
+			return;
+		}
+	};
+	let rewritten: BTreeSet<usize> = optimized
+		.rewrites
+		.iter()
+		.flat_map(|r| r.address..r.address + r.after.width())
+		.collect();
+	let (runs, optimized_time) = time(|| f(&optimized.memory, true));
+	if runs.len() != cached.len()
+		|| !cached
+			.iter()
+			.zip(&runs)
+			.all(|(a, b)| agrees(a, b, &rewritten))
+	{
+		eprintln!("bench: {}: optimized runs differ from the original", name);
+		process::exit(1);
+	}
+	let steps: u64 = cached.iter().map(|run| run.steps).sum();
+	let saved = steps - runs.iter().map(|run| run.steps).sum::<u64>();
+	println!(
+		"{}: {:?} optimized, {} instructions rewritten, {} of {} steps saved",
+		name,
+		optimized_time,
+		optimized.rewrites.len(),
+		saved,
+		steps
+	);
+}
+
+/// The program at `path`, or `None` if a default path is not there.
+fn load(path: Option<String>, default: &str) -> Option<Vec<i64>> {
+	let path = match path {
+		Some(path) => path,
+		None if Path::new(default).exists() => default.to_owned(),
+		None => {
+			println!("bench: skipping {}, which is not there", default);
+			return None;
+		}
+	};
+	let input = fs::read_to_string(&path).unwrap_or_else(|e| {
+		eprintln!("bench: {}: {}", path, e);
+		process::exit(1);
+	});
+	Some(intcode::parse(&input).unwrap_or_else(|e| {
+		eprintln!("bench: {}: invalid program: {}", path, e);
+		process::exit(1);
+	}))
+}
+
+fn main() {
+	let primes = asm::assemble(PRIMES).unwrap();
+	let inputs = [2000];
+	let run = |program: &[i64], cached| each_input(program, &inputs, cached);
+	bench("primes", &primes, run, Some(&inputs));
+
+	let mut args = env::args().skip(1);
+	if let Some(program) = load(args.next(), "input/2019/day5.txt") {
+		let inputs = [1, 5];
+		let run =
+			|program: &[i64], cached| each_input(program, &inputs, cached);
+		bench("day 5", &program, run, Some(&inputs));
+	}
+	if let Some(program) = load(args.next(), "input/2019/day7.txt") {
+		bench("day 7", &program, day07, None);
+	}
+}
@@ -0,0 +1,58 @@
+use advent_of_code_2019::intcode::{self, cfg::Graph};
+use std::ops::Range;
+use std::{env, fs, process};
+
+const USAGE: &str = "usage: cfg [--dot] FILE";
+
+fn fail(message: impl std::fmt::Display) -> ! {
+	eprintln!("cfg: {}", message);
+	process::exit(1);
+}
+
+fn ranges(ranges: &[Range<usize>]) -> String {
+	let ranges: Vec<String> = ranges
+		.iter()
+		.map(|r| format!("{}..{}", r.start, r.end))
+		.collect();
+	if ranges.is_empty() {
+		"none".to_owned()
+	} else {
+		ranges.join(", ")
+	}
+}
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let (dot, path) = match args.as_slice() {
+		[flag, path] if flag == "--dot" => (true, path),
+		[path] => (false, path),
+		_ => fail(USAGE),
+	};
+	let input = fs::read_to_string(path)
+		.unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+	let memory = intcode::parse(&input)
+		.unwrap_or_else(|e| fail(format!("invalid program: {}", e)));
+	let graph = Graph::build(&memory);
+	if dot {
+		print!("{}", graph.dot());
+		return;
+	}
+	for block in graph.blocks.values() {
+		let successors: Vec<String> =
+			block.successors().iter().map(|s| s.to_string()).collect();
+		println!(
+			"block {}..{} -> {:?} [{}]",
+			block.start,
+			block.end(),
+			block.exit,
+			successors.join(", ")
+		);
+	}
+	println!("code: {}", ranges(&graph.code()));
+	println!("data: {}", ranges(&graph.data()));
+	let unresolved: Vec<String> =
+		graph.unresolved().iter().map(|a| a.to_string()).collect();
+	if !unresolved.is_empty() {
+		println!("unresolved jumps at: {}", unresolved.join(", "));
+	}
+}
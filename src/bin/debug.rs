@@ -0,0 +1,38 @@
+use advent_of_code_2019::intcode::{
+	self, debugger::Debugger, replay, snapshot, Vm,
+};
+use std::{env, fs, io, process};
+
+fn main() {
+	let path = env::args().nth(1).unwrap_or_else(|| {
+		eprintln!("usage: debug FILE");
+		process::exit(1);
+	});
+	let input = fs::read_to_string(&path).unwrap_or_else(|e| {
+		eprintln!("debug: {}: {}", path, e);
+		process::exit(1);
+	});
+	let vm = if snapshot::is_snapshot(&input) {
+		snapshot::read(input.as_bytes()).unwrap_or_else(|e| {
+			eprintln!("debug: {}: {}", path, e);
+			process::exit(1);
+		})
+	} else if replay::is_recording(&input) {
+		let recording = replay::read(input.as_bytes()).unwrap_or_else(|e| {
+			eprintln!("debug: {}: {}", path, e);
+			process::exit(1);
+		});
+		recording.machine()
+	} else {
+		Vm::new(intcode::parse(&input).unwrap_or_else(|e| {
+			eprintln!("debug: invalid program: {}", e);
+			process::exit(1);
+		}))
+	};
+	let mut debugger = Debugger::new(vm);
+	let stdin = io::stdin();
+	if let Err(e) = debugger.repl(stdin.lock(), io::stdout()) {
+		eprintln!("debug: {}", e);
+		process::exit(1);
+	}
+}
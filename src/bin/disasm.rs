@@ -0,0 +1,25 @@
+use advent_of_code_2019::intcode::{self, disasm};
+use std::io::Read;
+use std::{env, fs, io, process};
+
+fn main() {
+	let input = match env::args().nth(1) {
+		Some(path) => fs::read_to_string(&path).unwrap_or_else(|e| {
+			eprintln!("disasm: {}: {}", path, e);
+			process::exit(1);
+		}),
+		None => {
+			let mut input = String::new();
+			io::stdin().read_to_string(&mut input).unwrap_or_else(|e| {
+				eprintln!("disasm: {}", e);
+				process::exit(1);
+			});
+			input
+		}
+	};
+	let memory = intcode::parse(&input).unwrap_or_else(|e| {
+		eprintln!("disasm: invalid program: {}", e);
+		process::exit(1);
+	});
+	print!("{}", disasm::disassemble(&memory));
+}
@@ -0,0 +1,55 @@
+use advent_of_code_2019::intcode::disasm;
+use advent_of_code_2019::intcode::fuzz::{self, Features, Rng};
+use std::{env, process};
+
+const USAGE: &str = "usage: fuzz [--seed N] [--runs N]";
+
+fn fail(message: impl std::fmt::Display) -> ! {
+	eprintln!("fuzz: {}", message);
+	process::exit(1);
+}
+
+fn main() {
+	let mut seed = 1;
+	let mut runs = 10_000;
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		let value = args.next().unwrap_or_else(|| fail(USAGE));
+		let value: u64 = value
+			.parse()
+			.unwrap_or_else(|_| fail(format!("invalid number '{}'", value)));
+		match arg.as_str() {
+			"--seed" => seed = value,
+			"--runs" => runs = value,
+			_ => fail(USAGE),
+		}
+	}
+
+	let mut rng = Rng::new(seed);
+	for run in 0..runs {
+		for &features in &Features::ALL {
+			let case = fuzz::generate(&mut rng, features);
+			if fuzz::check(&case).is_none() {
+				continue;
+			}
+			let case = fuzz::shrink(&case, |c| fuzz::check(c).is_some());
+			println!(
+				"run {} ({:?} features, seed {}): the engines disagree",
+				run, features, seed
+			);
+			let program: Vec<String> =
+				case.program.iter().map(|w| w.to_string()).collect();
+			println!("program: {}", program.join(","));
+			println!("input: {:?}", case.input);
+			print!("{}", disasm::disassemble(&case.program));
+			for (engine, outcome) in fuzz::check(&case).unwrap() {
+				println!("{:>10?}: {}", engine, outcome);
+			}
+			process::exit(1);
+		}
+	}
+	println!(
+		"{} programs, every engine agrees with the reference",
+		runs * Features::ALL.len() as u64
+	);
+}
@@ -0,0 +1,38 @@
+use advent_of_code_2019::intcode::{self, optimize::optimize};
+use std::io::{self, Read};
+use std::{env, fs, process};
+
+fn main() {
+	let input = match env::args().nth(1) {
+		Some(path) => fs::read_to_string(&path).unwrap_or_else(|e| {
+			eprintln!("optimize: {}: {}", path, e);
+			process::exit(1);
+		}),
+		None => {
+			let mut input = String::new();
+			io::stdin().read_to_string(&mut input).unwrap_or_else(|e| {
+				eprintln!("optimize: {}", e);
+				process::exit(1);
+			});
+			input
+		}
+	};
+	let memory = intcode::parse(&input).unwrap_or_else(|e| {
+		eprintln!("optimize: invalid program: {}", e);
+		process::exit(1);
+	});
+	let optimized = optimize(&memory).unwrap_or_else(|refusal| {
+		eprintln!("optimize: left unchanged, as {}", refusal);
+		process::exit(1);
+	});
+	for rewrite in &optimized.rewrites {
+		eprintln!("{}", rewrite);
+	}
+	eprintln!(
+		"optimize: {} instructions rewritten",
+		optimized.rewrites.len()
+	);
+	let words: Vec<String> =
+		optimized.memory.iter().map(|w| w.to_string()).collect();
+	println!("{}", words.join(","));
+}
@@ -0,0 +1,68 @@
+use advent_of_code_2019::intcode::amplifier::Circuit;
+use advent_of_code_2019::intcode::{self, State, Vm};
+use std::{env, fs, process};
+
+const USAGE: &str = "\
+usage: profile FILE [INPUT...]
+       profile --chain|--ring FILE PHASE,PHASE,...";
+
+fn fail(message: impl std::fmt::Display) -> ! {
+	eprintln!("profile: {}", message);
+	process::exit(1);
+}
+
+fn parse_values(arg: &str) -> Vec<i64> {
+	arg.split(',')
+		.map(|v| {
+			v.trim()
+				.parse()
+				.unwrap_or_else(|_| fail(format!("invalid value '{}'", v)))
+		})
+		.collect()
+}
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let (topology, rest) = match args.first().map(String::as_str) {
+		Some("--chain") | Some("--ring") => {
+			(Some(args[0].as_str()), &args[1..])
+		}
+		_ => (None, &args[..]),
+	};
+	let path = rest.first().unwrap_or_else(|| fail(USAGE));
+	let input = fs::read_to_string(path)
+		.unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+	let program = intcode::parse(&input)
+		.unwrap_or_else(|e| fail(format!("invalid program: {}", e)));
+
+	let profile = match topology {
+		Some(topology) => {
+			let alphabet =
+				parse_values(rest.get(1).unwrap_or_else(|| fail(USAGE)));
+			let circuit = if topology == "--ring" {
+				Circuit::ring(alphabet.len())
+			} else {
+				Circuit::chain(alphabet.len())
+			};
+			circuit
+				.profile(&program, &alphabet)
+				.unwrap_or_else(|e| fail(e))
+		}
+		None => {
+			let mut vm = Vm::new(program.clone());
+			vm.set_profiling(true);
+			for arg in &rest[1..] {
+				for value in parse_values(arg) {
+					vm.push_input(value);
+				}
+			}
+			match vm.run() {
+				Ok(State::Halted) => {}
+				Ok(state) => eprintln!("profile: stopped: {:?}", state),
+				Err(fault) => eprintln!("profile: {}", fault),
+			}
+			vm.take_profile().unwrap()
+		}
+	};
+	print!("{}", profile.report(&program, 20));
+}
@@ -0,0 +1,90 @@
+use advent_of_code_2019::intcode::amplifier::Circuit;
+use advent_of_code_2019::intcode::{self, replay, Vm};
+use std::{env, fs, process};
+
+const USAGE: &str = "\
+usage: record FILE LOG [INPUT...]
+       record --chain|--ring FILE PHASE,PHASE,... LOG";
+
+fn fail(message: impl std::fmt::Display) -> ! {
+	eprintln!("record: {}", message);
+	process::exit(1);
+}
+
+fn parse_values(arg: &str) -> Vec<i64> {
+	arg.split(',')
+		.map(|v| {
+			v.trim()
+				.parse()
+				.unwrap_or_else(|_| fail(format!("invalid value '{}'", v)))
+		})
+		.collect()
+}
+
+fn save(recording: &replay::Recording, path: &str) {
+	replay::save(recording, path)
+		.unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+	if let Some((step, why)) = &recording.stop {
+		eprintln!("record: {}: stopped at step {}: {}", path, step, why);
+	}
+}
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let (topology, rest) = match args.first().map(String::as_str) {
+		Some("--chain") | Some("--ring") => {
+			(Some(args[0].as_str()), &args[1..])
+		}
+		_ => (None, &args[..]),
+	};
+	let path = rest.first().unwrap_or_else(|| fail(USAGE));
+	let input = fs::read_to_string(path)
+		.unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+	let program = intcode::parse(&input)
+		.unwrap_or_else(|e| fail(format!("invalid program: {}", e)));
+
+	match topology {
+		Some(topology) => {
+			let phases =
+				parse_values(rest.get(1).unwrap_or_else(|| fail(USAGE)));
+			let log = rest.get(2).unwrap_or_else(|| fail(USAGE));
+			let circuit = if topology == "--ring" {
+				Circuit::ring(phases.len())
+			} else {
+				Circuit::chain(phases.len())
+			};
+			let recordings = circuit.record(&program, &phases);
+			for (i, recording) in recordings.iter().enumerate() {
+				save(recording, &format!("{}.{}", log, i));
+			}
+			let signal = recordings.last().and_then(|recording| {
+				recording.events.iter().rev().find_map(
+					|&(_, event)| match event {
+						replay::Event::Output(value) => Some(value),
+						replay::Event::Input(_) => None,
+					},
+				)
+			});
+			match signal {
+				Some(signal) => println!("{}", signal),
+				None => fail("the last amplifier produced no signal"),
+			}
+		}
+		None => {
+			let log = rest.get(1).unwrap_or_else(|| fail(USAGE));
+			let mut vm = Vm::new(program);
+			vm.set_recording(true);
+			for arg in &rest[2..] {
+				for value in parse_values(arg) {
+					vm.push_input(value);
+				}
+			}
+			// How the run ended goes in the recording.
+			let _ = vm.run();
+			for value in vm.read_output() {
+				println!("{}", value);
+			}
+			save(&vm.take_recording().unwrap(), log);
+		}
+	}
+}
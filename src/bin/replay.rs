@@ -0,0 +1,27 @@
+use advent_of_code_2019::intcode::replay;
+use std::{env, process};
+
+fn main() {
+	let paths: Vec<String> = env::args().skip(1).collect();
+	if paths.is_empty() {
+		eprintln!("usage: replay LOG...");
+		process::exit(1);
+	}
+	let mut diverged = false;
+	for path in &paths {
+		let recording = replay::load(path).unwrap_or_else(|e| {
+			eprintln!("replay: {}: {}", path, e);
+			process::exit(1);
+		});
+		match replay::replay(&recording) {
+			Ok(steps) => println!("{}: ok, {} steps", path, steps),
+			Err(divergence) => {
+				println!("{}: diverged at {}", path, divergence);
+				diverged = true;
+			}
+		}
+	}
+	if diverged {
+		process::exit(1);
+	}
+}
@@ -0,0 +1,90 @@
+use advent_of_code_2019::intcode::device::{
+	Clock, Console, Framebuffer, Random,
+};
+use advent_of_code_2019::intcode::io::{Input, Output};
+use advent_of_code_2019::intcode::{self, ascii, io, State, Vm};
+use std::{env, fs, process};
+
+const USAGE: &str = "usage: run [--ascii] [--devices] FILE";
+
+/// Where `--devices` maps each device.
+const CLOCK: usize = 1000;
+const RANDOM: usize = 1001;
+const CONSOLE: usize = 1002;
+const FRAMEBUFFER: usize = 1024;
+const SCREEN_WIDTH: usize = 40;
+const SCREEN_HEIGHT: usize = 20;
+
+fn main() {
+	let mut text = false;
+	let mut devices = false;
+	let mut path = None;
+	for arg in env::args().skip(1) {
+		match arg.as_str() {
+			"--ascii" => text = true,
+			"--devices" => devices = true,
+			_ if path.is_none() => path = Some(arg),
+			_ => {
+				eprintln!("{}", USAGE);
+				process::exit(1);
+			}
+		}
+	}
+	let path = path.unwrap_or_else(|| {
+		eprintln!("{}", USAGE);
+		process::exit(1);
+	});
+	let input = fs::read_to_string(&path).unwrap_or_else(|e| {
+		eprintln!("run: {}: {}", path, e);
+		process::exit(1);
+	});
+	let memory = intcode::parse(&input).unwrap_or_else(|e| {
+		eprintln!("run: invalid program: {}", e);
+		process::exit(1);
+	});
+	let (mut input, mut output): (Box<dyn Input>, Box<dyn Output>) = if text {
+		(
+			Box::new(ascii::StdinLines::new()),
+			Box::new(ascii::StdoutText::new()),
+		)
+	} else {
+		(Box::new(io::Stdin::new()), Box::new(io::Stdout))
+	};
+
+	let mut vm = Vm::new(memory);
+	let screen = if devices {
+		vm.map_device(CLOCK, Clock::new());
+		vm.map_device(RANDOM, Random::new(0));
+		vm.map_device(CONSOLE, Console::echoing());
+		Some(vm.map_device(
+			FRAMEBUFFER,
+			Framebuffer::new(SCREEN_WIDTH, SCREEN_HEIGHT),
+		))
+	} else {
+		None
+	};
+	let result = vm.run_with(&mut *input, &mut *output);
+	if let Some(screen) = screen {
+		let screen = screen.lock().unwrap();
+		if (0..SCREEN_HEIGHT)
+			.any(|y| (0..SCREEN_WIDTH).any(|x| screen.pixel(x, y) != 0))
+		{
+			print!("{}", screen.render());
+		}
+	}
+	match result {
+		Ok(State::Halted) => {}
+		Ok(State::AwaitingInput) => {
+			eprintln!("run: input ended while the program awaits more");
+			process::exit(1);
+		}
+		Ok(state) => {
+			eprintln!("run: stopped: {:?}", state);
+			process::exit(1);
+		}
+		Err(fault) => {
+			eprintln!("run: {}", fault);
+			process::exit(1);
+		}
+	}
+}
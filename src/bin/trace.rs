@@ -0,0 +1,44 @@
+use advent_of_code_2019::intcode::trace::{Format, Tracer};
+use advent_of_code_2019::intcode::{self, State, Vm};
+use std::{env, fs, io, process};
+
+fn main() {
+	let mut args: Vec<String> = env::args().skip(1).collect();
+	let format = match args.iter().position(|a| a == "--json") {
+		Some(i) => {
+			args.remove(i);
+			Format::JsonLines
+		}
+		None => Format::Text,
+	};
+	if args.is_empty() {
+		eprintln!("usage: trace [--json] FILE [INPUT...]");
+		process::exit(1);
+	}
+	let input = fs::read_to_string(&args[0]).unwrap_or_else(|e| {
+		eprintln!("trace: {}: {}", args[0], e);
+		process::exit(1);
+	});
+	let memory = intcode::parse(&input).unwrap_or_else(|e| {
+		eprintln!("trace: invalid program: {}", e);
+		process::exit(1);
+	});
+	let mut vm = Vm::new(memory);
+	for arg in &args[1..] {
+		vm.push_input(arg.parse().unwrap_or_else(|_| {
+			eprintln!("trace: invalid input value '{}'", arg);
+			process::exit(1);
+		}));
+	}
+	let stdout = io::stdout();
+	let mut tracer = Tracer::new(stdout.lock(), format);
+	match tracer.run(&mut vm) {
+		Ok(State::Halted) => {}
+		Ok(State::AwaitingInput) => eprintln!("trace: program awaits input"),
+		Ok(state) => eprintln!("trace: stopped: {:?}", state),
+		Err(e) => {
+			eprintln!("trace: {}", e);
+			process::exit(1);
+		}
+	}
+}
@@ -1,59 +1,255 @@
+use crate::intcode::{self, State, Vm};
 use aoc_runner_derive::{aoc, aoc_generator};
 
+/// Far more steps than any noun and verb the puzzle intends should take.
+const STEP_BUDGET: u64 = 100_000;
+const TARGET: i64 = 19690720;
+
 #[aoc_generator(day2)]
-fn g(input: &str) -> Vec<usize> {
-	input.split(',').map(|x| x.parse().unwrap()).collect()
+fn g(input: &str) -> Vec<i64> {
+	intcode::parse(input).unwrap_or_else(|e| panic!("invalid program: {}", e))
 }
 
 #[aoc(day2, part1)]
-fn s1(instructions: &[usize]) -> usize {
-	let mut memory = vec![0; instructions.len()];
-	memory.copy_from_slice(instructions);
-	memory[1] = 12;
-	memory[2] = 2;
-	execute_program(&mut memory)
+fn s1(instructions: &[i64]) -> i64 {
+	execute_program(instructions, 12, 2).unwrap()
 }
 
 #[aoc(day2, part2)]
-fn s2(instructions: &[usize]) -> usize {
-	let mut memory = vec![0; instructions.len()];
-	memory.copy_from_slice(instructions);
+fn s2(instructions: &[i64]) -> i64 {
+	match execute_symbolically(instructions) {
+		Ok(result) => match solve(instructions, result) {
+			Some(answer) => return answer,
+			None => eprintln!(
+				"day 2: memory[0] = {} never reaches {}, searching instead",
+				result, TARGET
+			),
+		},
+		Err(reason) => {
+			eprintln!(
+				"day 2: cannot solve symbolically ({}), searching",
+				reason
+			)
+		}
+	}
 	for noun in 0..100 {
 		for verb in 0..100 {
-			memory[1] = noun;
-			memory[2] = verb;
-			let result = execute_program(&mut memory);
-			if result == 19690720 {
+			let result = execute_program(instructions, noun, verb);
+			if result == Some(TARGET) {
 				return 100 * noun + verb;
-			} else {
-				memory.copy_from_slice(instructions);
 			}
 		}
 	}
 	unreachable!()
 }
 
-fn execute_program(memory: &mut [usize]) -> usize {
-	let mut i = 0;
-	loop {
-		match memory[i] {
-			1 => {
-				let a = memory[i + 1];
-				let b = memory[i + 2];
-				let c = memory[i + 3];
-				memory[c] = memory[a] + memory[b];
-				i += 4;
+/// Returns `None` if the program faults or does not halt within the budget.
+fn execute_program(instructions: &[i64], noun: i64, verb: i64) -> Option<i64> {
+	let mut vm = Vm::new(instructions.to_vec());
+	vm.memory_mut()[1] = noun;
+	vm.memory_mut()[2] = verb;
+	vm.set_budget(Some(STEP_BUDGET));
+	match vm.run() {
+		Ok(State::Halted) => Some(vm.memory()[0]),
+		_ => None,
+	}
+}
+
+/// `constant + noun_coefficient * noun + verb_coefficient * verb`, for the
+/// unknown noun and verb.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Affine {
+	constant: i64,
+	noun_coefficient: i64,
+	verb_coefficient: i64,
+}
+
+impl Affine {
+	fn constant(value: i64) -> Self {
+		Affine {
+			constant: value,
+			noun_coefficient: 0,
+			verb_coefficient: 0,
+		}
+	}
+
+	fn as_constant(self) -> Option<i64> {
+		if self.noun_coefficient == 0 && self.verb_coefficient == 0 {
+			Some(self.constant)
+		} else {
+			None
+		}
+	}
+
+	fn add(self, other: Affine) -> Option<Affine> {
+		Some(Affine {
+			constant: self.constant.checked_add(other.constant)?,
+			noun_coefficient: self
+				.noun_coefficient
+				.checked_add(other.noun_coefficient)?,
+			verb_coefficient: self
+				.verb_coefficient
+				.checked_add(other.verb_coefficient)?,
+		})
+	}
+
+	fn scale(self, by: i64) -> Option<Affine> {
+		Some(Affine {
+			constant: self.constant.checked_mul(by)?,
+			noun_coefficient: self.noun_coefficient.checked_mul(by)?,
+			verb_coefficient: self.verb_coefficient.checked_mul(by)?,
+		})
+	}
+
+	fn multiply(self, other: Affine) -> Option<Affine> {
+		match (self.as_constant(), other.as_constant()) {
+			(Some(c), _) => other.scale(c),
+			(_, Some(c)) => self.scale(c),
+			_ => None,
+		}
+	}
+}
+
+impl std::fmt::Display for Affine {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(
+			f,
+			"{} + {} * noun + {} * verb",
+			self.constant, self.noun_coefficient, self.verb_coefficient
+		)
+	}
+}
+
+/// Runs the program with the noun and verb left unknown and returns what ends
+/// up in `memory[0]` as a function of them. A word that is not an affine
+/// function of the noun and verb, such as their product or a word read from
+/// an address one of them chose, is tracked as `None`; that is only an error
+/// if the program later needs it as an opcode, an address or the result.
+fn execute_symbolically(instructions: &[i64]) -> Result<Affine, String> {
+	let mut memory: Vec<Option<Affine>> = instructions
+		.iter()
+		.map(|&word| Some(Affine::constant(word)))
+		.collect();
+	if memory.len() < 3 {
+		return Err("the program has no noun and verb".to_owned());
+	}
+	memory[1] = Some(Affine {
+		constant: 0,
+		noun_coefficient: 1,
+		verb_coefficient: 0,
+	});
+	memory[2] = Some(Affine {
+		constant: 0,
+		noun_coefficient: 0,
+		verb_coefficient: 1,
+	});
+
+	let word = |memory: &[Option<Affine>], address: usize| {
+		memory
+			.get(address)
+			.copied()
+			.flatten()
+			.and_then(Affine::as_constant)
+			.ok_or_else(|| {
+				format!("the word at {} depends on the noun or verb", address)
+			})
+	};
+	let address = |memory: &[Option<Affine>], at: usize| {
+		let address = word(memory, at)?;
+		if address < 0 || address as usize >= memory.len() {
+			return Err(format!(
+				"address {} at {} is out of range",
+				address, at
+			));
+		}
+		Ok(address as usize)
+	};
+	// An operand read from an address that depends on the noun or verb could
+	// be any word, so it is unknown rather than an error.
+	let operand = |memory: &[Option<Affine>], at: usize| match memory
+		.get(at)
+		.copied()
+		.flatten()
+		.and_then(Affine::as_constant)
+	{
+		Some(_) => Ok(memory[address(memory, at)?]),
+		None if at < memory.len() => Ok(None),
+		None => Err(format!("the program runs past its end at {}", at)),
+	};
+
+	let mut pointer = 0;
+	for _ in 0..STEP_BUDGET {
+		let opcode = word(&memory, pointer)?;
+		if opcode == 99 {
+			return memory[0].ok_or_else(|| {
+				"memory[0] is not affine in the noun and verb".to_owned()
+			});
+		}
+		if opcode != 1 && opcode != 2 {
+			return Err(format!(
+				"opcode {} at {} is not add or multiply",
+				opcode, pointer
+			));
+		}
+		let a = operand(&memory, pointer + 1)?;
+		let b = operand(&memory, pointer + 2)?;
+		let c = address(&memory, pointer + 3)?;
+		memory[c] = match (a, b) {
+			(Some(a), Some(b)) if opcode == 1 => a.add(b),
+			(Some(a), Some(b)) => a.multiply(b),
+			_ => None,
+		};
+		pointer += 4;
+	}
+	Err("the program did not halt within the step budget".to_owned())
+}
+
+/// Finds the noun and verb below 100 for which `result` reaches the target,
+/// confirming each candidate by running the program.
+fn solve(instructions: &[i64], result: Affine) -> Option<i64> {
+	for noun in 0..100 {
+		let rest =
+			match result.noun_coefficient.checked_mul(noun).and_then(|n| {
+				TARGET.checked_sub(result.constant)?.checked_sub(n)
+			}) {
+				Some(rest) => rest,
+				None => continue,
+			};
+		let verbs = if result.verb_coefficient == 0 {
+			if rest != 0 {
+				continue;
 			}
-			2 => {
-				let a = memory[i + 1];
-				let b = memory[i + 2];
-				let c = memory[i + 3];
-				memory[c] = memory[a] * memory[b];
-				i += 4;
+			0..100
+		} else {
+			match rest.checked_rem(result.verb_coefficient) {
+				Some(0) => {
+					let verb = rest / result.verb_coefficient;
+					verb..verb + 1
+				}
+				_ => continue,
+			}
+		};
+		for verb in verbs.filter(|v| (0..100).contains(v)) {
+			if execute_program(instructions, noun, verb) == Some(TARGET) {
+				return Some(100 * noun + verb);
 			}
-			99 => break,
-			_ => panic!("invalid opcode"),
 		}
 	}
-	memory[0]
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn solve_skips_a_noun_whose_division_overflows() {
+		// For noun 1 this leaves i64::MIN to divide by -1.
+		let result = Affine {
+			constant: TARGET + 1,
+			noun_coefficient: i64::MAX,
+			verb_coefficient: -1,
+		};
+		assert_eq!(solve(&[99, 0, 0, 0], result), None);
+	}
 }
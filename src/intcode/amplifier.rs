@@ -0,0 +1,187 @@
+use super::network::{Network, Report};
+use super::profile::Profile;
+use super::replay::Recording;
+use super::{Fault, Vm};
+
+/// Amplifiers running copies of one program, wired together by directed
+/// edges. Each amplifier is first given its phase setting; then `input`
+/// receives a signal of 0 and the circuit's signal is the last value `output`
+/// produces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Circuit {
+	amplifiers: usize,
+	edges: Vec<(usize, usize)>,
+	input: usize,
+	output: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Best {
+	pub phases: Vec<i64>,
+	pub signal: i64,
+}
+
+impl Circuit {
+	pub fn new(
+		amplifiers: usize,
+		edges: Vec<(usize, usize)>,
+		input: usize,
+		output: usize,
+	) -> Self {
+		assert!(input < amplifiers && output < amplifiers);
+		assert!(edges.iter().all(|&(a, b)| a < amplifiers && b < amplifiers));
+		Circuit {
+			amplifiers,
+			edges,
+			input,
+			output,
+		}
+	}
+
+	/// Each amplifier feeds the next.
+	pub fn chain(amplifiers: usize) -> Self {
+		assert!(amplifiers > 0, "a circuit needs at least one amplifier");
+		let edges = (1..amplifiers).map(|i| (i - 1, i)).collect();
+		Circuit::new(amplifiers, edges, 0, amplifiers - 1)
+	}
+
+	/// A chain whose last amplifier also feeds the first.
+	pub fn ring(amplifiers: usize) -> Self {
+		let mut circuit = Circuit::chain(amplifiers);
+		circuit.edges.push((amplifiers - 1, 0));
+		circuit
+	}
+
+	/// The first amplifier feeds every amplifier in between, which all feed
+	/// the last.
+	pub fn fan_out(amplifiers: usize) -> Self {
+		assert!(amplifiers >= 3);
+		let last = amplifiers - 1;
+		let edges = (1..last).flat_map(|i| vec![(0, i), (i, last)]).collect();
+		Circuit::new(amplifiers, edges, 0, last)
+	}
+
+	pub fn amplifiers(&self) -> usize {
+		self.amplifiers
+	}
+
+	/// Runs the circuit with the given phase settings, one per amplifier.
+	/// Returns `None` if the output amplifier never produces a value.
+	pub fn signal(
+		&self,
+		program: &[i64],
+		phases: &[i64],
+	) -> Result<Option<i64>, Fault> {
+		let mut report = self.run(program, phases, |_| {});
+		for machine in &report.machines {
+			machine.result?;
+		}
+		Ok(report.machines.swap_remove(self.output).outputs.pop())
+	}
+
+	/// Runs the circuit with recording turned on, and returns each
+	/// amplifier's recording in order, however the run ended.
+	pub fn record(&self, program: &[i64], phases: &[i64]) -> Vec<Recording> {
+		let report = self.run(program, phases, |vm| vm.set_recording(true));
+		report
+			.machines
+			.into_iter()
+			.map(|mut machine| machine.vm.take_recording().unwrap())
+			.collect()
+	}
+
+	fn run(
+		&self,
+		program: &[i64],
+		phases: &[i64],
+		configure: impl Fn(&mut Vm),
+	) -> Report {
+		assert_eq!(phases.len(), self.amplifiers);
+		let mut network = Network::new();
+		for &phase in phases {
+			let mut vm = Vm::new(program.to_vec());
+			configure(&mut vm);
+			let i = network.add(vm);
+			network.send(i, phase);
+		}
+		for &(from, to) in &self.edges {
+			network.connect(from, to);
+		}
+		network.send(self.input, 0);
+		// Several amplifiers can feed one, and only running them in turn fixes
+		// the order their signals arrive in, and so the signal.
+		network.run_in_turn()
+	}
+
+	/// Tries every assignment of distinct phase settings drawn from
+	/// `alphabet` and returns the one producing the strongest signal.
+	pub fn best(
+		&self,
+		program: &[i64],
+		alphabet: &[i64],
+	) -> Result<Option<Best>, Fault> {
+		let mut best: Option<Best> = None;
+		for phases in permutations(alphabet, self.amplifiers) {
+			if let Some(signal) = self.signal(program, &phases)? {
+				let better = match &best {
+					Some(b) => signal > b.signal,
+					None => true,
+				};
+				if better {
+					best = Some(Best { phases, signal });
+				}
+			}
+		}
+		Ok(best)
+	}
+
+	/// Runs the same search as `best` with profiling turned on, and returns
+	/// the profile of every amplifier in every run merged together.
+	pub fn profile(
+		&self,
+		program: &[i64],
+		alphabet: &[i64],
+	) -> Result<Profile, Fault> {
+		let mut profile = Profile::default();
+		for phases in permutations(alphabet, self.amplifiers) {
+			let mut report =
+				self.run(program, &phases, |vm| vm.set_profiling(true));
+			for machine in &mut report.machines {
+				machine.result?;
+				profile.merge(&machine.vm.take_profile().unwrap());
+			}
+		}
+		Ok(profile)
+	}
+}
+
+/// Every ordered selection of `k` distinct elements of `choices`.
+pub fn permutations(choices: &[i64], k: usize) -> Vec<Vec<i64>> {
+	let mut permutations = Vec::new();
+	let mut queue = vec![(Vec::with_capacity(k), choices.to_vec())];
+	while let Some((a, b)) = queue.pop() {
+		if a.len() == k {
+			permutations.push(a);
+		} else {
+			for (i, &choice) in b.iter().enumerate().rev() {
+				let mut selected = a.clone();
+				let mut choices = b.clone();
+				choices.remove(i);
+				selected.push(choice);
+				queue.push((selected, choices));
+			}
+		}
+	}
+	permutations
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[should_panic(expected = "a circuit needs at least one amplifier")]
+	fn ring_rejects_no_amplifiers() {
+		Circuit::ring(0);
+	}
+}
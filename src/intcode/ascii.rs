@@ -0,0 +1,297 @@
+//! Talking to programs that read and write ASCII text, one character per
+//! value. Output values outside the ASCII range are kept as raw numbers,
+//! which is how such programs usually report a final answer.
+
+use super::io::{Input, Output};
+use super::{Fault, State, Vm};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// A run of output: either text or a single value that is not ASCII.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Chunk {
+	Text(String),
+	Value(i64),
+}
+
+fn is_ascii(value: i64) -> bool {
+	(0..=127).contains(&value)
+}
+
+/// The first character of some text that could not be sent, as it is not
+/// ASCII.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NotAscii(pub char);
+
+impl fmt::Display for NotAscii {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?} is not ASCII", self.0)
+	}
+}
+
+impl std::error::Error for NotAscii {}
+
+/// Why a command got no reply: its text was not ASCII, or the machine
+/// faulted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommandError {
+	NotAscii(NotAscii),
+	Fault(Fault),
+}
+
+impl fmt::Display for CommandError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			CommandError::NotAscii(e) => e.fmt(f),
+			CommandError::Fault(fault) => fault.fmt(f),
+		}
+	}
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<NotAscii> for CommandError {
+	fn from(e: NotAscii) -> Self {
+		CommandError::NotAscii(e)
+	}
+}
+
+impl From<Fault> for CommandError {
+	fn from(fault: Fault) -> Self {
+		CommandError::Fault(fault)
+	}
+}
+
+/// Encodes text as one input value per character.
+pub fn encode(text: &str) -> Result<Vec<i64>, NotAscii> {
+	match text.chars().find(|c| !c.is_ascii()) {
+		Some(c) => Err(NotAscii(c)),
+		None => Ok(text.bytes().map(i64::from).collect()),
+	}
+}
+
+/// Groups output values into runs of text and the raw values between them.
+pub fn decode(values: &[i64]) -> Vec<Chunk> {
+	let mut chunks = Vec::new();
+	for &value in values {
+		if !is_ascii(value) {
+			chunks.push(Chunk::Value(value));
+			continue;
+		}
+		let c = value as u8 as char;
+		match chunks.last_mut() {
+			Some(Chunk::Text(text)) => text.push(c),
+			_ => chunks.push(Chunk::Text(c.to_string())),
+		}
+	}
+	chunks
+}
+
+/// What a program said before it stopped or reached the prompt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reply {
+	pub chunks: Vec<Chunk>,
+	/// Why the machine stopped, or `None` if it reached the prompt it was
+	/// being read up to and may have more to say.
+	pub state: Option<State>,
+}
+
+impl Reply {
+	/// All the text, with any raw values left out.
+	pub fn text(&self) -> String {
+		self.chunks
+			.iter()
+			.filter_map(|chunk| match chunk {
+				Chunk::Text(text) => Some(text.as_str()),
+				Chunk::Value(_) => None,
+			})
+			.collect()
+	}
+
+	/// The raw values, in order.
+	pub fn values(&self) -> Vec<i64> {
+		self.chunks
+			.iter()
+			.filter_map(|chunk| match *chunk {
+				Chunk::Value(value) => Some(value),
+				Chunk::Text(_) => None,
+			})
+			.collect()
+	}
+}
+
+/// A machine driven with text.
+pub struct Ascii {
+	vm: Vm,
+}
+
+impl Ascii {
+	pub fn new(vm: Vm) -> Self {
+		Ascii { vm }
+	}
+
+	pub fn vm(&self) -> &Vm {
+		&self.vm
+	}
+
+	pub fn vm_mut(&mut self) -> &mut Vm {
+		&mut self.vm
+	}
+
+	pub fn into_inner(self) -> Vm {
+		self.vm
+	}
+
+	/// Queues `text` as input. Nothing is queued if it is not ASCII.
+	pub fn send(&mut self, text: &str) -> Result<(), NotAscii> {
+		for value in encode(text)? {
+			self.vm.push_input(value);
+		}
+		Ok(())
+	}
+
+	/// Queues `line` followed by a newline.
+	pub fn send_line(&mut self, line: &str) -> Result<(), NotAscii> {
+		self.send(line)?;
+		self.send("\n")
+	}
+
+	/// Runs until the machine stops.
+	pub fn run(&mut self) -> Result<Reply, Fault> {
+		let state = self.vm.run()?;
+		Ok(Reply {
+			chunks: decode(&self.vm.read_output()),
+			state: Some(state),
+		})
+	}
+
+	/// Runs until the machine's text output ends with `prompt`, or until it
+	/// stops. Output after the prompt is left for the next read.
+	pub fn read_until(&mut self, prompt: &str) -> Result<Reply, Fault> {
+		let mut output = Vec::new();
+		// The text written since the last value that was not ASCII.
+		let mut tail = String::new();
+		loop {
+			let state = self.vm.step()?;
+			for value in self.vm.read_output() {
+				if is_ascii(value) {
+					tail.push(value as u8 as char);
+				} else {
+					tail.clear();
+				}
+				output.push(value);
+			}
+			if state.is_some() || (!prompt.is_empty() && tail.ends_with(prompt))
+			{
+				return Ok(Reply {
+					chunks: decode(&output),
+					state,
+				});
+			}
+		}
+	}
+
+	/// Sends `line` as a command and reads the reply up to `prompt`.
+	pub fn command(
+		&mut self,
+		line: &str,
+		prompt: &str,
+	) -> Result<Reply, CommandError> {
+		self.send_line(line)?;
+		Ok(self.read_until(prompt)?)
+	}
+}
+
+/// Feeds lines read from standard input as text, newline included.
+#[derive(Default)]
+pub struct StdinLines {
+	pending: VecDeque<i64>,
+}
+
+impl StdinLines {
+	pub fn new() -> Self {
+		StdinLines::default()
+	}
+}
+
+impl Input for StdinLines {
+	fn read(&mut self) -> Option<i64> {
+		if self.pending.is_empty() {
+			let mut line = String::new();
+			match io::stdin().lock().read_line(&mut line) {
+				Ok(0) | Err(_) => return None,
+				Ok(_) => {}
+			}
+			if !line.is_ascii() {
+				eprintln!("ignoring input that is not ASCII");
+				line.retain(|c| c.is_ascii());
+			}
+			if !line.ends_with('\n') {
+				line.push('\n');
+			}
+			self.pending.extend(line.bytes().map(i64::from));
+		}
+		self.pending.pop_front()
+	}
+}
+
+/// Writes ASCII values to standard output as text, and any other value as a
+/// number on its own line.
+pub struct StdoutText {
+	at_line_start: bool,
+}
+
+impl StdoutText {
+	pub fn new() -> Self {
+		StdoutText::default()
+	}
+}
+
+impl Default for StdoutText {
+	fn default() -> Self {
+		StdoutText {
+			at_line_start: true,
+		}
+	}
+}
+
+impl Output for StdoutText {
+	fn write(&mut self, value: i64) {
+		let stdout = io::stdout();
+		let mut stdout = stdout.lock();
+		if is_ascii(value) {
+			let _ = stdout.write_all(&[value as u8]);
+			self.at_line_start = value == i64::from(b'\n');
+		} else {
+			let newline = if self.at_line_start { "" } else { "\n" };
+			let _ = writeln!(stdout, "{}{}", newline, value);
+			self.at_line_start = true;
+		}
+		let _ = stdout.flush();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn names_the_character_that_is_not_ascii() {
+		assert_eq!(encode("ok\n"), Ok(vec![111, 107, 10]));
+		assert_eq!(encode("naïve café"), Err(NotAscii('ï')));
+		assert_eq!(NotAscii('ï').to_string(), "'ï' is not ASCII");
+	}
+
+	#[test]
+	fn sends_nothing_that_is_not_ascii() {
+		// Echoes one character.
+		let mut ascii = Ascii::new(Vm::new(vec![3, 0, 4, 0, 99]));
+		assert_eq!(
+			ascii.command("→", ""),
+			Err(CommandError::NotAscii(NotAscii('→')))
+		);
+		assert_eq!(ascii.send_line("é"), Err(NotAscii('é')));
+		assert_eq!(ascii.command("x", "").unwrap().text(), "x");
+	}
+}
@@ -0,0 +1,504 @@
+//! A small assembly language for Intcode.
+//!
+//! ```text
+//! ; read a number and print it doubled
+//! start:  IN [x]
+//!         MUL [x], #2, [x]
+//!         OUT [x]
+//!         JT #1, #start
+//! x:      DB 0
+//! ```
+//!
+//! Operands are written `#value` for immediate mode, `[value]` for position
+//! mode and `rb+offset` for relative mode, where a value is a number, a label
+//! or a label plus or minus a number. `DB` emits raw data words and `;` starts
+//! a comment.
+
+use super::{Instruction, Mode, Parameter};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+	pub line: usize,
+	/// Counted in characters, from 1.
+	pub column: usize,
+	pub message: String,
+}
+
+impl Error {
+	fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+		Error {
+			line,
+			column,
+			message: message.into(),
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}: {}", self.line, self.column, self.message)
+	}
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+	Ident(String),
+	Number(i64),
+	Colon,
+	Comma,
+	Hash,
+	LBracket,
+	RBracket,
+	Plus,
+	Minus,
+}
+
+fn tokenize(line: usize, text: &str) -> Result<Vec<(usize, Token)>, Error> {
+	let mut tokens = Vec::new();
+	let mut chars = text.char_indices().peekable();
+	while let Some((i, c)) = chars.next() {
+		let column = text[..i].chars().count() + 1;
+		let token = match c {
+			';' => break,
+			c if c.is_whitespace() => continue,
+			':' => Token::Colon,
+			',' => Token::Comma,
+			'#' => Token::Hash,
+			'[' => Token::LBracket,
+			']' => Token::RBracket,
+			'+' => Token::Plus,
+			'-' => Token::Minus,
+			c if c.is_ascii_digit() => {
+				let mut end = i + 1;
+				while let Some(&(j, c)) = chars.peek() {
+					if !c.is_ascii_digit() {
+						break;
+					}
+					end = j + 1;
+					chars.next();
+				}
+				match text[i..end].parse() {
+					Ok(n) => Token::Number(n),
+					Err(_) => {
+						return Err(Error::new(
+							line,
+							column,
+							"number too large",
+						))
+					}
+				}
+			}
+			c if c.is_alphabetic() || c == '_' || c == '.' => {
+				let mut end = i + c.len_utf8();
+				while let Some(&(j, c)) = chars.peek() {
+					if !(c.is_alphanumeric() || c == '_' || c == '.') {
+						break;
+					}
+					end = j + c.len_utf8();
+					chars.next();
+				}
+				Token::Ident(text[i..end].to_owned())
+			}
+			c => {
+				return Err(Error::new(
+					line,
+					column,
+					format!("unexpected character '{}'", c),
+				))
+			}
+		};
+		tokens.push((column, token));
+	}
+	Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+	Number(i64),
+	Label(String, i64),
+}
+
+#[derive(Clone, Debug)]
+struct Operand {
+	column: usize,
+	mode: Mode,
+	value: Value,
+}
+
+#[derive(Debug)]
+enum Body {
+	Instruction(String, Vec<Operand>),
+	Data(Vec<(usize, Value)>),
+}
+
+#[derive(Debug)]
+struct Statement {
+	line: usize,
+	labels: Vec<(usize, String)>,
+	body: Option<Body>,
+}
+
+struct Parser {
+	line: usize,
+	end: usize,
+	tokens: Vec<(usize, Token)>,
+	position: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.position).map(|(_, t)| t)
+	}
+
+	fn column(&self) -> usize {
+		self.tokens.get(self.position).map_or(self.end, |&(c, _)| c)
+	}
+
+	fn error(&self, message: impl Into<String>) -> Error {
+		Error::new(self.line, self.column(), message)
+	}
+
+	fn next(&mut self) -> Option<Token> {
+		let token = self.tokens.get(self.position).map(|(_, t)| t.clone());
+		self.position += 1;
+		token
+	}
+
+	fn expect(&mut self, token: Token, what: &str) -> Result<(), Error> {
+		if self.peek() == Some(&token) {
+			self.position += 1;
+			Ok(())
+		} else {
+			Err(self.error(format!("expected {}", what)))
+		}
+	}
+
+	fn number(&mut self) -> Result<i64, Error> {
+		let negative = self.peek() == Some(&Token::Minus);
+		if negative {
+			self.position += 1;
+		}
+		match self.peek() {
+			Some(&Token::Number(n)) => {
+				self.position += 1;
+				Ok(if negative { -n } else { n })
+			}
+			_ => Err(self.error("expected number")),
+		}
+	}
+
+	fn offset(&mut self) -> Result<i64, Error> {
+		match self.peek() {
+			Some(Token::Plus) => {
+				self.position += 1;
+				self.number()
+			}
+			Some(Token::Minus) => self.number(),
+			_ => Ok(0),
+		}
+	}
+
+	fn value(&mut self) -> Result<Value, Error> {
+		match self.peek() {
+			Some(Token::Ident(_)) => match self.next() {
+				Some(Token::Ident(label)) => {
+					Ok(Value::Label(label, self.offset()?))
+				}
+				_ => unreachable!(),
+			},
+			_ => Ok(Value::Number(self.number()?)),
+		}
+	}
+
+	fn operand(&mut self) -> Result<Operand, Error> {
+		let column = self.column();
+		let (mode, value) = match self.peek() {
+			Some(Token::Hash) => {
+				self.position += 1;
+				(Mode::Immediate, self.value()?)
+			}
+			Some(Token::LBracket) => {
+				self.position += 1;
+				let value = self.value()?;
+				self.expect(Token::RBracket, "']'")?;
+				(Mode::Position, value)
+			}
+			Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case("rb") => {
+				self.position += 1;
+				(Mode::Relative, Value::Number(self.offset()?))
+			}
+			_ => {
+				return Err(self
+					.error("expected operand ('#value', '[value]' or 'rb+n')"))
+			}
+		};
+		Ok(Operand {
+			column,
+			mode,
+			value,
+		})
+	}
+
+	fn list<T>(
+		&mut self,
+		mut item: impl FnMut(&mut Self) -> Result<T, Error>,
+	) -> Result<Vec<T>, Error> {
+		let mut items = Vec::new();
+		if self.peek().is_none() {
+			return Ok(items);
+		}
+		loop {
+			items.push(item(self)?);
+			match self.peek() {
+				None => return Ok(items),
+				Some(Token::Comma) => self.position += 1,
+				_ => return Err(self.error("expected ','")),
+			}
+		}
+	}
+}
+
+fn arity(mnemonic: &str) -> Option<usize> {
+	match mnemonic {
+		"ADD" | "MUL" | "LT" | "EQ" => Some(3),
+		"JT" | "JF" => Some(2),
+		"IN" | "OUT" | "ARB" => Some(1),
+		"HLT" => Some(0),
+		_ => None,
+	}
+}
+
+fn build(mnemonic: &str, p: &[Parameter]) -> Instruction {
+	match mnemonic {
+		"ADD" => Instruction::Add(p[0], p[1], p[2]),
+		"MUL" => Instruction::Multiply(p[0], p[1], p[2]),
+		"IN" => Instruction::Input(p[0]),
+		"OUT" => Instruction::Output(p[0]),
+		"JT" => Instruction::JumpIfTrue(p[0], p[1]),
+		"JF" => Instruction::JumpIfFalse(p[0], p[1]),
+		"LT" => Instruction::LessThan(p[0], p[1], p[2]),
+		"EQ" => Instruction::Equals(p[0], p[1], p[2]),
+		"ARB" => Instruction::AdjustRelativeBase(p[0]),
+		"HLT" => Instruction::Halt,
+		_ => unreachable!(),
+	}
+}
+
+fn parse_line(line: usize, text: &str) -> Result<Statement, Error> {
+	let mut parser = Parser {
+		line,
+		end: text.chars().count() + 1,
+		tokens: tokenize(line, text)?,
+		position: 0,
+	};
+	let mut labels = Vec::new();
+	while let (Some(Token::Ident(_)), Some((_, Token::Colon))) =
+		(parser.peek(), parser.tokens.get(parser.position + 1))
+	{
+		let column = parser.column();
+		if let Some(Token::Ident(label)) = parser.next() {
+			labels.push((column, label));
+		}
+		parser.position += 1;
+	}
+	let column = parser.column();
+	let mnemonic = match parser.next() {
+		None => {
+			return Ok(Statement {
+				line,
+				labels,
+				body: None,
+			})
+		}
+		Some(Token::Ident(mnemonic)) => mnemonic.to_ascii_uppercase(),
+		Some(_) => return Err(Error::new(line, column, "expected mnemonic")),
+	};
+	let body = if mnemonic == "DB" {
+		Body::Data(parser.list(|p| Ok((p.column(), p.value()?)))?)
+	} else {
+		let arity = arity(&mnemonic).ok_or_else(|| {
+			Error::new(line, column, format!("unknown mnemonic '{}'", mnemonic))
+		})?;
+		let operands = parser.list(Parser::operand)?;
+		if operands.len() != arity {
+			return Err(Error::new(
+				line,
+				column,
+				format!(
+					"{} takes {} operand(s), found {}",
+					mnemonic,
+					arity,
+					operands.len()
+				),
+			));
+		}
+		Body::Instruction(mnemonic, operands)
+	};
+	Ok(Statement {
+		line,
+		labels,
+		body: Some(body),
+	})
+}
+
+/// Assembles `source` into an Intcode image.
+pub fn assemble(source: &str) -> Result<Vec<i64>, Error> {
+	let mut labels = HashMap::new();
+	let mut statements = Vec::new();
+	let mut address = 0;
+	for (i, text) in source.lines().enumerate() {
+		let line = i + 1;
+		let statement = parse_line(line, text)?;
+		for (column, label) in &statement.labels {
+			if labels.insert(label.clone(), address as i64).is_some() {
+				return Err(Error::new(
+					line,
+					*column,
+					format!("duplicate label '{}'", label),
+				));
+			}
+		}
+		address += match &statement.body {
+			Some(Body::Instruction(_, operands)) => operands.len() + 1,
+			Some(Body::Data(values)) => values.len(),
+			None => 0,
+		};
+		statements.push(statement);
+	}
+
+	let resolve = |line: usize, column: usize, value: &Value| match value {
+		Value::Number(n) => Ok(*n),
+		Value::Label(label, offset) => match labels.get(label) {
+			Some(address) => address.checked_add(*offset).ok_or_else(|| {
+				Error::new(
+					line,
+					column,
+					format!("'{}' plus its offset is out of range", label),
+				)
+			}),
+			None => Err(Error::new(
+				line,
+				column,
+				format!("undefined label '{}'", label),
+			)),
+		},
+	};
+
+	let mut image = Vec::with_capacity(address);
+	for Statement { line, body, .. } in &statements {
+		match body {
+			None => {}
+			Some(Body::Data(values)) => {
+				for (column, value) in values {
+					image.push(resolve(*line, *column, value)?);
+				}
+			}
+			Some(Body::Instruction(mnemonic, operands)) => {
+				let mut parameters = Vec::with_capacity(operands.len());
+				for operand in operands {
+					parameters.push(Parameter {
+						mode: operand.mode,
+						value: resolve(*line, operand.column, &operand.value)?,
+					});
+				}
+				let instruction = build(mnemonic, &parameters);
+				if let Some(Parameter {
+					mode: Mode::Immediate,
+					..
+				}) = instruction.destination()
+				{
+					let column = operands.last().unwrap().column;
+					return Err(Error::new(
+						*line,
+						column,
+						"cannot write through an immediate operand",
+					));
+				}
+				image.extend(instruction.encode());
+			}
+		}
+	}
+	Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::intcode::disasm;
+
+	/// Disassembles `image` back into source the assembler accepts.
+	fn source(image: &[i64]) -> String {
+		disasm::lines(image, 0)
+			.map(|line| match line.instruction {
+				Some(instruction) => format!("{}\n", instruction),
+				None => format!("DB {}\n", line.words[0]),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn round_trips_through_the_disassembler() {
+		let doubler = assemble(
+			"; read a number and print it doubled
+			start:  IN [x]
+			        MUL [x], #2, [x]
+			        OUT [x]
+			        JT #1, #start
+			x:      DB 0",
+		)
+		.unwrap();
+		assert_eq!(doubler, [3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0]);
+		let relative = assemble("ARB #5\nADD rb-1, rb+2, rb+0\nHLT").unwrap();
+		let day5 = crate::intcode::parse(
+			"3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,\
+			 98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,\
+			 1000,1,20,4,20,1105,1,46,98,99",
+		)
+		.unwrap();
+		for image in [doubler, relative, day5].iter() {
+			assert_eq!(&assemble(&source(image)).unwrap(), image);
+		}
+	}
+
+	#[test]
+	fn resolves_labels_with_offsets() {
+		let image = assemble("a: DB b-1, b, b+2\nb: DB a+1").unwrap();
+		assert_eq!(image, [2, 3, 5, 1]);
+	}
+
+	#[test]
+	fn reports_undefined_and_duplicate_labels() {
+		assert_eq!(
+			assemble("JT #1, #nowhere"),
+			Err(Error::new(1, 8, "undefined label 'nowhere'"))
+		);
+		assert_eq!(
+			assemble("a: DB 0\na: DB 1"),
+			Err(Error::new(2, 1, "duplicate label 'a'"))
+		);
+	}
+
+	#[test]
+	fn rejects_an_offset_past_the_largest_word() {
+		assert_eq!(
+			assemble("DB 0\nx: DB x+9223372036854775807"),
+			Err(Error::new(2, 7, "'x' plus its offset is out of range"))
+		);
+	}
+
+	#[test]
+	fn counts_columns_in_characters() {
+		assert_eq!(
+			assemble("é: ADD #1, #2, #3"),
+			Err(Error::new(
+				1,
+				16,
+				"cannot write through an immediate operand"
+			))
+		);
+	}
+}
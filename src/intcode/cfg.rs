@@ -0,0 +1,291 @@
+//! Static control-flow analysis of an Intcode image.
+//!
+//! Starting from address 0, every instruction that can be reached is decoded,
+//! following both ways out of a conditional jump whose target is immediate.
+//! Jumps through memory or the relative base are recorded as unresolved, and
+//! anything they might reach is not explored. The analysis also ignores
+//! self-modifying code: it sees the image as it is before the program runs.
+
+use super::{Instruction, Mode, Parameter};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+use std::ops::Range;
+
+/// Where control may go after a block's last instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+	/// Falls through into the block starting at this address.
+	Next(usize),
+	/// Jumps to `target` if the condition holds, otherwise falls through to
+	/// `next`. A condition known statically leaves only one of the two.
+	Branch {
+		target: Option<usize>,
+		next: Option<usize>,
+	},
+	/// Jumps to an address only known when the program runs, falling through
+	/// to `next` if the jump may not be taken.
+	Unresolved {
+		next: Option<usize>,
+	},
+	Halt,
+	/// The word at this address cannot be decoded as an instruction, or the
+	/// instruction runs past the end of the image.
+	Invalid(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+	pub start: usize,
+	pub instructions: Vec<(usize, Instruction)>,
+	pub exit: Exit,
+}
+
+impl Block {
+	/// The address just past the block's last instruction.
+	pub fn end(&self) -> usize {
+		match self.instructions.last() {
+			Some(&(address, instruction)) => address + instruction.width(),
+			None => self.start,
+		}
+	}
+
+	pub fn successors(&self) -> Vec<usize> {
+		match self.exit {
+			Exit::Next(next) => vec![next],
+			Exit::Branch { target, next } => {
+				target.into_iter().chain(next).collect()
+			}
+			Exit::Unresolved { next } => next.into_iter().collect(),
+			Exit::Halt | Exit::Invalid(_) => vec![],
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Graph {
+	/// Basic blocks by start address.
+	pub blocks: BTreeMap<usize, Block>,
+	len: usize,
+	code: BTreeSet<usize>,
+}
+
+/// The value of a parameter if it is known without running the program.
+fn constant(p: Parameter) -> Option<i64> {
+	if p.mode == Mode::Immediate {
+		Some(p.value)
+	} else {
+		None
+	}
+}
+
+fn decode(memory: &[i64], address: usize) -> Option<Instruction> {
+	Instruction::decode(address, memory)
+		.ok()
+		.filter(|i| address + i.width() <= memory.len())
+}
+
+/// Where control goes after `instruction`: `Some` jump target, itself `None`
+/// if unknown, when the instruction may jump, and whether it may fall through.
+fn flow(instruction: &Instruction) -> (Option<Option<usize>>, bool) {
+	let (condition, target, jump_if) = match *instruction {
+		Instruction::JumpIfTrue(a, b) => (a, b, true),
+		Instruction::JumpIfFalse(a, b) => (a, b, false),
+		Instruction::Halt => return (None, false),
+		_ => return (None, true),
+	};
+	let (taken, not_taken) = match constant(condition) {
+		Some(c) => ((c != 0) == jump_if, (c != 0) != jump_if),
+		None => (true, true),
+	};
+	let target = constant(target).filter(|&t| t >= 0).map(|t| t as usize);
+	(if taken { Some(target) } else { None }, not_taken)
+}
+
+impl Graph {
+	pub fn build(memory: &[i64]) -> Self {
+		let mut instructions = BTreeMap::new();
+		let mut invalid = BTreeSet::new();
+		let mut leaders = BTreeSet::new();
+		let mut pending = vec![0];
+		leaders.insert(0);
+		while let Some(address) = pending.pop() {
+			if instructions.contains_key(&address) || invalid.contains(&address)
+			{
+				continue;
+			}
+			let instruction = match decode(memory, address) {
+				Some(instruction) => instruction,
+				None => {
+					invalid.insert(address);
+					continue;
+				}
+			};
+			instructions.insert(address, instruction);
+			let next = address + instruction.width();
+			let (jump, falls_through) = flow(&instruction);
+			if let Some(target) = jump {
+				leaders.insert(next);
+				if let Some(target) = target {
+					leaders.insert(target);
+					pending.push(target);
+				}
+			}
+			if falls_through {
+				pending.push(next);
+			}
+		}
+
+		let mut code = BTreeSet::new();
+		for (&address, instruction) in &instructions {
+			code.extend(address..address + instruction.width());
+		}
+
+		let mut blocks = BTreeMap::new();
+		let starts: Vec<usize> = leaders
+			.iter()
+			.copied()
+			.filter(|a| instructions.contains_key(a) || invalid.contains(a))
+			.collect();
+		for start in starts {
+			let mut block = Block {
+				start,
+				instructions: Vec::new(),
+				exit: Exit::Halt,
+			};
+			let mut address = start;
+			block.exit = loop {
+				let instruction = match instructions.get(&address) {
+					Some(&instruction) => instruction,
+					None => break Exit::Invalid(address),
+				};
+				block.instructions.push((address, instruction));
+				let next = address + instruction.width();
+				let (jump, falls_through) = flow(&instruction);
+				let next_exit = if falls_through { Some(next) } else { None };
+				match jump {
+					Some(Some(target)) => {
+						break Exit::Branch {
+							target: Some(target),
+							next: next_exit,
+						}
+					}
+					Some(None) => break Exit::Unresolved { next: next_exit },
+					None if !falls_through => break Exit::Halt,
+					None if leaders.contains(&next) => break Exit::Next(next),
+					None => address = next,
+				}
+			};
+			blocks.insert(start, block);
+		}
+
+		Graph {
+			blocks,
+			len: memory.len(),
+			code,
+		}
+	}
+
+	/// Whether some reachable instruction occupies `address`.
+	pub fn is_code(&self, address: usize) -> bool {
+		self.code.contains(&address)
+	}
+
+	/// Maximal runs of addresses that are reachable code.
+	pub fn code(&self) -> Vec<Range<usize>> {
+		self.ranges(true)
+	}
+
+	/// Maximal runs of addresses no reachable instruction occupies, which the
+	/// program can only use as data.
+	pub fn data(&self) -> Vec<Range<usize>> {
+		self.ranges(false)
+	}
+
+	fn ranges(&self, code: bool) -> Vec<Range<usize>> {
+		let mut ranges: Vec<Range<usize>> = Vec::new();
+		for address in 0..self.len {
+			if self.is_code(address) != code {
+				continue;
+			}
+			match ranges.last_mut() {
+				Some(range) if range.end == address => range.end += 1,
+				_ => ranges.push(address..address + 1),
+			}
+		}
+		ranges
+	}
+
+	/// Addresses of jumps whose target is only known at run time.
+	pub fn unresolved(&self) -> Vec<usize> {
+		self.blocks
+			.values()
+			.filter(|b| matches!(b.exit, Exit::Unresolved { .. }))
+			.filter_map(|b| b.instructions.last().map(|&(address, _)| address))
+			.collect()
+	}
+
+	/// Renders the graph in Graphviz DOT, one box per block.
+	pub fn dot(&self) -> String {
+		let mut out = String::new();
+		self.write_dot(&mut out).unwrap();
+		out
+	}
+
+	fn write_dot(&self, out: &mut impl Write) -> fmt::Result {
+		writeln!(out, "digraph intcode {{")?;
+		writeln!(out, "\tnode [shape=box, fontname=monospace];")?;
+		for block in self.blocks.values() {
+			let mut label = String::new();
+			for (address, instruction) in &block.instructions {
+				write!(label, "{:>6}  {}\\l", address, instruction)?;
+			}
+			if let Exit::Invalid(address) = block.exit {
+				write!(label, "{:>6}  invalid\\l", address)?;
+			}
+			writeln!(out, "\tb{} [label=\"{}\"];", block.start, label)?;
+		}
+		let mut unresolved = false;
+		for block in self.blocks.values() {
+			let from = block.start;
+			match block.exit {
+				Exit::Next(next) => writeln!(out, "\tb{} -> b{};", from, next)?,
+				Exit::Branch { target, next } => {
+					if let Some(target) = target {
+						writeln!(
+							out,
+							"\tb{} -> b{} [label=\"taken\"];",
+							from, target
+						)?;
+					}
+					if let Some(next) = next {
+						writeln!(
+							out,
+							"\tb{} -> b{} [label=\"not taken\", style=dashed];",
+							from, next
+						)?;
+					}
+				}
+				Exit::Unresolved { next } => {
+					unresolved = true;
+					writeln!(
+						out,
+						"\tb{} -> unresolved [label=\"taken\"];",
+						from
+					)?;
+					if let Some(next) = next {
+						writeln!(
+							out,
+							"\tb{} -> b{} [label=\"not taken\", style=dashed];",
+							from, next
+						)?;
+					}
+				}
+				Exit::Halt | Exit::Invalid(_) => {}
+			}
+		}
+		if unresolved {
+			writeln!(out, "\tunresolved [label=\"?\", shape=circle];")?;
+		}
+		writeln!(out, "}}")
+	}
+}
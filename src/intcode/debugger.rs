@@ -0,0 +1,415 @@
+use super::{disasm, snapshot, Fault, State, Vm};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+commands:
+  s, step [N]          execute N instructions (default 1)
+  c, continue          run until a breakpoint, halt or input is needed,
+                       or for 10 million instructions
+  bs, back [N]         undo the last N instructions (default 1)
+  rewind STEP          go back or forward to instruction number STEP
+  lastwrite ADDR       go back to the last instruction that wrote ADDR
+  b, break ADDR        set a breakpoint
+  d, delete ADDR       remove a breakpoint
+  breakpoints          list breakpoints
+  r, registers         show the pointer, relative base and step count
+  m, mem ADDR [LEN]    dump LEN words of memory (default 16)
+  l, list [ADDR] [N]   disassemble N lines around ADDR (default: pointer)
+  i, input VALUE...    queue input values
+  save FILE            write a snapshot of the machine to FILE
+  h, help              show this message
+  q, quit              leave the debugger";
+
+/// Why execution stopped after a `step` or `continue`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+	Stepped,
+	Breakpoint(usize),
+	State(State),
+	Fault(Fault),
+	/// Going back reached the oldest instruction left in the journal.
+	JournalStart,
+	/// `continue` ran `CONTINUE_LIMIT` instructions without stopping.
+	StillRunning,
+}
+
+/// How much memory the debugger lets the journal for stepping back use.
+pub const JOURNAL_LIMIT: usize = 64 << 20;
+
+/// How many instructions `continue` runs before handing back control, so a
+/// program stuck in a loop cannot hang the debugger.
+pub const CONTINUE_LIMIT: u64 = 10_000_000;
+
+/// The most words `mem` shows at once.
+const MEM_LIMIT: usize = 1 << 16;
+
+pub struct Debugger {
+	vm: Vm,
+	breakpoints: BTreeSet<usize>,
+}
+
+impl Debugger {
+	/// Takes over `vm`, turning on its journal so that execution can be
+	/// stepped back.
+	pub fn new(mut vm: Vm) -> Self {
+		vm.set_journal(Some(JOURNAL_LIMIT));
+		Debugger {
+			vm,
+			breakpoints: BTreeSet::new(),
+		}
+	}
+
+	pub fn vm(&self) -> &Vm {
+		&self.vm
+	}
+
+	pub fn vm_mut(&mut self) -> &mut Vm {
+		&mut self.vm
+	}
+
+	pub fn breakpoints(&self) -> &BTreeSet<usize> {
+		&self.breakpoints
+	}
+
+	pub fn set_breakpoint(&mut self, address: usize) -> bool {
+		self.breakpoints.insert(address)
+	}
+
+	pub fn delete_breakpoint(&mut self, address: usize) -> bool {
+		self.breakpoints.remove(&address)
+	}
+
+	pub fn step(&mut self) -> Stop {
+		match self.vm.step() {
+			Ok(Some(state)) => Stop::State(state),
+			Ok(None) => Stop::Stepped,
+			Err(fault) => Stop::Fault(fault),
+		}
+	}
+
+	pub fn step_back(&mut self) -> Stop {
+		if self.vm.step_back() {
+			Stop::Stepped
+		} else {
+			Stop::JournalStart
+		}
+	}
+
+	/// Steps back or forward until `step` instructions have been executed.
+	pub fn rewind(&mut self, step: u64) -> Stop {
+		while self.vm.steps() > step {
+			if !self.vm.step_back() {
+				return Stop::JournalStart;
+			}
+		}
+		while self.vm.steps() < step {
+			match self.step() {
+				Stop::Stepped => {}
+				stop => return stop,
+			}
+		}
+		Stop::Stepped
+	}
+
+	/// Steps back to the last instruction that wrote to `address`, leaving it
+	/// under the pointer.
+	pub fn rewind_to_write(&mut self, address: usize) -> Stop {
+		match self.vm.journal().and_then(|j| j.last_write(address)) {
+			Some(step) => self.rewind(step),
+			None => Stop::JournalStart,
+		}
+	}
+
+	/// Runs until the machine stops on its own, reaches a breakpoint or has
+	/// run `CONTINUE_LIMIT` instructions. The instruction under the pointer is
+	/// always executed, so continuing from a breakpoint makes progress.
+	pub fn resume(&mut self) -> Stop {
+		// The limit is spent from the machine's budget, and whatever the
+		// machine's own budget has left is given back afterwards.
+		let budget = self.vm.budget();
+		let limit = budget.map_or(CONTINUE_LIMIT, |b| b.min(CONTINUE_LIMIT));
+		self.vm.set_budget(Some(limit));
+		let stop = loop {
+			match self.step() {
+				Stop::Stepped => {}
+				stop => break stop,
+			}
+			let pointer = self.vm.pointer();
+			if self.breakpoints.contains(&pointer) {
+				break Stop::Breakpoint(pointer);
+			}
+		};
+		let used = limit - self.vm.budget().unwrap_or(0);
+		let budget = budget.map(|b| b - used);
+		self.vm.set_budget(budget);
+		match stop {
+			Stop::State(State::BudgetExhausted { .. }) if budget != Some(0) => {
+				Stop::StillRunning
+			}
+			stop => stop,
+		}
+	}
+
+	/// Disassembles `count` lines around `address`. Up to a third of them
+	/// precede it, provided a sweep from the start of memory lands on
+	/// `address`; otherwise the listing starts at `address`.
+	pub fn list(&self, address: usize, count: usize) -> Vec<String> {
+		let memory = self.vm.memory();
+		let before = count / 3;
+		let mut start = address;
+		let mut previous = Vec::new();
+		for line in disasm::lines(memory, 0) {
+			if line.address >= address {
+				if line.address == address {
+					start = previous[previous.len().saturating_sub(before)..]
+						.first()
+						.copied()
+						.unwrap_or(address);
+				}
+				break;
+			}
+			previous.push(line.address);
+		}
+		disasm::lines(memory, start)
+			.take(count)
+			.map(|line| {
+				let marker = match line.address {
+					a if a == self.vm.pointer() => "=>",
+					a if self.breakpoints.contains(&a) => " *",
+					_ => "  ",
+				};
+				format!("{}{}", marker, line)
+			})
+			.collect()
+	}
+
+	fn execute(
+		&mut self,
+		command: &str,
+		args: &[&str],
+	) -> Result<Vec<String>, String> {
+		let mut out = Vec::new();
+		match command {
+			"s" | "step" => {
+				let n = args.first().map_or(Ok(1), |a| parse(a))?;
+				let mut stop = Stop::Stepped;
+				for _ in 0..n {
+					stop = self.step();
+					if stop != Stop::Stepped {
+						break;
+					}
+				}
+				out.extend(self.report(stop));
+			}
+			"bs" | "back" => {
+				let n = args.first().map_or(Ok(1), |a| parse(a))?;
+				let mut stop = Stop::Stepped;
+				for _ in 0..n {
+					stop = self.step_back();
+					if stop != Stop::Stepped {
+						break;
+					}
+				}
+				out.extend(self.report(stop));
+			}
+			"rewind" => {
+				let step = parse(args.first().ok_or("usage: rewind STEP")?)?;
+				let stop = self.rewind(step as u64);
+				out.extend(self.report(stop));
+			}
+			"lastwrite" => {
+				let address =
+					parse(args.first().ok_or("usage: lastwrite ADDR")?)?;
+				let stop = self.rewind_to_write(address);
+				if stop == Stop::JournalStart {
+					return Err(format!(
+						"no write to {} in the journal",
+						address
+					));
+				}
+				out.extend(self.report(stop));
+			}
+			"c" | "continue" => {
+				let stop = self.resume();
+				out.extend(self.report(stop));
+			}
+			"b" | "break" => {
+				let address = parse(args.first().ok_or("usage: break ADDR")?)?;
+				self.set_breakpoint(address);
+				out.push(format!("breakpoint at {}", address));
+			}
+			"d" | "delete" => {
+				let address = parse(args.first().ok_or("usage: delete ADDR")?)?;
+				if !self.delete_breakpoint(address) {
+					return Err(format!("no breakpoint at {}", address));
+				}
+			}
+			"breakpoints" => {
+				out.extend(self.breakpoints.iter().map(|a| a.to_string()));
+			}
+			"r" | "registers" => {
+				out.push(format!("pointer       {}", self.vm.pointer()));
+				out.push(format!("relative base {}", self.vm.relative_base()));
+				out.push(format!("step          {}", self.vm.steps()));
+			}
+			"m" | "mem" => {
+				let address =
+					parse(args.first().ok_or("usage: mem ADDR [LEN]")?)?;
+				let len = args.get(1).map_or(Ok(16), |a| parse(a))?;
+				if len > MEM_LIMIT {
+					return Err(format!(
+						"can show at most {} words at a time",
+						MEM_LIMIT
+					));
+				}
+				let end = address.saturating_add(len);
+				for row in (address..end).step_by(8) {
+					let words: Vec<String> =
+						(row..row.saturating_add(8).min(end))
+							.map(|a| format!("{:>8}", self.vm.peek(a)))
+							.collect();
+					out.push(format!("{:>6}: {}", row, words.join(" ")));
+				}
+			}
+			"l" | "list" => {
+				let address =
+					args.first().map_or(Ok(self.vm.pointer()), |a| parse(a))?;
+				let count = args.get(1).map_or(Ok(10), |a| parse(a))?;
+				out.extend(self.list(address, count));
+			}
+			"i" | "input" => {
+				if args.is_empty() {
+					return Err("usage: input VALUE...".to_owned());
+				}
+				for arg in args {
+					let value = arg
+						.parse()
+						.map_err(|_| format!("invalid value '{}'", arg))?;
+					self.vm.push_input(value);
+				}
+			}
+			"save" => {
+				let path = args.first().ok_or("usage: save FILE")?;
+				snapshot::save(&self.vm, path)
+					.map_err(|e| format!("{}: {}", path, e))?;
+				out.push(format!("saved to {}", path));
+			}
+			"h" | "help" => out.push(HELP.to_owned()),
+			_ => {
+				return Err(format!(
+					"unknown command '{}', try 'help'",
+					command
+				))
+			}
+		}
+		Ok(out)
+	}
+
+	fn report(&mut self, stop: Stop) -> Vec<String> {
+		let mut out: Vec<String> = self
+			.vm
+			.read_output()
+			.iter()
+			.map(|value| format!("output: {}", value))
+			.collect();
+		match stop {
+			Stop::Stepped => {}
+			Stop::Breakpoint(address) => {
+				out.push(format!("breakpoint at {}", address))
+			}
+			Stop::State(State::Halted) => out.push("halted".to_owned()),
+			Stop::State(State::AwaitingInput) => {
+				out.push("awaiting input, queue some with 'input'".to_owned())
+			}
+			Stop::State(State::BudgetExhausted { .. }) => {
+				out.push("step budget exhausted".to_owned())
+			}
+			Stop::State(State::InfiniteLoop { period, .. }) => {
+				out.push(format!("infinite loop with period {}", period))
+			}
+			Stop::Fault(fault) => out.push(format!("fault: {}", fault)),
+			Stop::StillRunning => out.push(format!(
+				"still running after {} instructions, continue to go on",
+				CONTINUE_LIMIT
+			)),
+			Stop::JournalStart => out.push(format!(
+				"the journal goes back no further than step {}",
+				self.vm.steps()
+			)),
+		}
+		out.extend(self.list(self.vm.pointer(), 1));
+		out
+	}
+
+	/// Reads commands from `input` until it ends or `quit` is entered. An
+	/// empty line repeats the previous command.
+	pub fn repl(
+		&mut self,
+		input: impl BufRead,
+		mut output: impl Write,
+	) -> io::Result<()> {
+		let mut previous = String::new();
+		let mut lines = input.lines();
+		loop {
+			write!(output, "(icdb) ")?;
+			output.flush()?;
+			let line = match lines.next() {
+				Some(line) => line?,
+				None => break,
+			};
+			let line = if line.trim().is_empty() {
+				previous.clone()
+			} else {
+				line
+			};
+			let words: Vec<&str> = line.split_whitespace().collect();
+			let (command, args) = match words.split_first() {
+				Some((&"q", _)) | Some((&"quit", _)) => break,
+				Some((command, args)) => (*command, args),
+				None => continue,
+			};
+			match self.execute(command, args) {
+				Ok(lines) => {
+					for line in lines {
+						writeln!(output, "{}", line)?;
+					}
+				}
+				Err(e) => writeln!(output, "error: {}", e)?,
+			}
+			previous = line;
+		}
+		Ok(())
+	}
+}
+
+fn parse(arg: &str) -> Result<usize, String> {
+	arg.parse().map_err(|_| format!("invalid number '{}'", arg))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn mem_stops_at_the_last_address() {
+		let mut debugger = Debugger::new(Vm::new(vec![99]));
+		let out = debugger.execute("mem", &["18446744073709551613"]).unwrap();
+		let zero = format!("{:>8}", 0);
+		assert_eq!(out, [format!("{:>6}: {} {}", usize::MAX - 2, zero, zero)]);
+	}
+
+	#[test]
+	fn continue_gives_control_back_from_an_endless_loop() {
+		let mut debugger = Debugger::new(Vm::new(vec![1105, 1, 0]));
+		debugger.vm_mut().set_budget(Some(CONTINUE_LIMIT + 5));
+		assert_eq!(debugger.resume(), Stop::StillRunning);
+		assert_eq!(debugger.vm().steps(), CONTINUE_LIMIT);
+		assert_eq!(debugger.vm().budget(), Some(5));
+		assert!(matches!(
+			debugger.resume(),
+			Stop::State(State::BudgetExhausted { .. })
+		));
+		assert_eq!(debugger.vm().steps(), CONTINUE_LIMIT + 5);
+	}
+}
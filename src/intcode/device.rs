@@ -0,0 +1,342 @@
+//! Peripherals mapped into a machine's address space. Reads and writes to a
+//! mapped range go to the device instead of memory, so a program can drive a
+//! display or read a clock with the ordinary instruction set. Instructions
+//! are always fetched from memory, never from a device.
+
+use super::rng::Rng;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// A peripheral occupying `size()` consecutive addresses. Offsets passed to
+/// its methods count from the first of them.
+pub trait Device: Send {
+	/// The number of addresses the device occupies.
+	fn size(&self) -> usize;
+
+	/// The word at `offset` as a debugger or tracer would show it, without
+	/// side effects.
+	fn peek(&self, offset: usize) -> i64;
+
+	/// The word at `offset` as the running program reads it. Reading may have
+	/// side effects, such as taking a key from a keyboard buffer.
+	fn read(&mut self, offset: usize) -> i64 {
+		self.peek(offset)
+	}
+
+	fn write(&mut self, offset: usize, value: i64);
+}
+
+#[derive(Clone)]
+struct Mapping {
+	start: usize,
+	size: usize,
+	device: Arc<Mutex<dyn Device>>,
+}
+
+/// The devices mapped into one machine.
+#[derive(Clone, Default)]
+pub(super) struct Bus {
+	mappings: Vec<Mapping>,
+	/// Reads and writes made by the running program, so the machine can tell
+	/// when a step touched a device.
+	accesses: u64,
+}
+
+impl fmt::Debug for Bus {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_list()
+			.entries(self.mappings.iter().map(|m| m.start..m.start + m.size))
+			.finish()
+	}
+}
+
+impl Bus {
+	pub(super) fn map<D: Device + 'static>(
+		&mut self,
+		start: usize,
+		device: D,
+	) -> Arc<Mutex<D>> {
+		let size = device.size();
+		assert!(size > 0, "a device must occupy at least one address");
+		let end = start.checked_add(size).expect("device range overflows");
+		assert!(
+			self.mappings
+				.iter()
+				.all(|m| end <= m.start || m.start + m.size <= start),
+			"device at {}..{} overlaps another",
+			start,
+			end
+		);
+		let device = Arc::new(Mutex::new(device));
+		self.mappings.push(Mapping {
+			start,
+			size,
+			device: device.clone(),
+		});
+		device
+	}
+
+	fn find(&self, address: usize) -> Option<(&Mapping, usize)> {
+		self.mappings
+			.iter()
+			.find(|m| m.start <= address && address < m.start + m.size)
+			.map(|m| (m, address - m.start))
+	}
+
+	pub(super) fn accesses(&self) -> u64 {
+		self.accesses
+	}
+
+	pub(super) fn peek(&self, address: usize) -> Option<i64> {
+		let (mapping, offset) = self.find(address)?;
+		Some(mapping.device.lock().unwrap().peek(offset))
+	}
+
+	pub(super) fn read(&mut self, address: usize) -> Option<i64> {
+		let (mapping, offset) = self.find(address)?;
+		let value = mapping.device.lock().unwrap().read(offset);
+		self.accesses += 1;
+		Some(value)
+	}
+
+	/// Returns whether a device took the write.
+	pub(super) fn write(&mut self, address: usize, value: i64) -> bool {
+		match self.find(address) {
+			Some((mapping, offset)) => {
+				mapping.device.lock().unwrap().write(offset, value);
+				self.accesses += 1;
+				true
+			}
+			None => false,
+		}
+	}
+}
+
+/// Reads as the milliseconds since the clock was created. Writes are ignored.
+pub struct Clock {
+	start: Instant,
+}
+
+impl Clock {
+	pub fn new() -> Self {
+		Clock {
+			start: Instant::now(),
+		}
+	}
+}
+
+impl Default for Clock {
+	fn default() -> Self {
+		Clock::new()
+	}
+}
+
+impl Device for Clock {
+	fn size(&self) -> usize {
+		1
+	}
+
+	fn peek(&self, _: usize) -> i64 {
+		self.start.elapsed().as_millis() as i64
+	}
+
+	fn write(&mut self, _: usize, _: i64) {}
+}
+
+/// Each read gives the next of a repeatable sequence of non-negative
+/// pseudo-random numbers. Writing a value reseeds the sequence.
+pub struct Random {
+	rng: Rng,
+}
+
+impl Random {
+	pub fn new(seed: i64) -> Self {
+		Random {
+			rng: Rng::new(seed as u64),
+		}
+	}
+}
+
+impl Device for Random {
+	fn size(&self) -> usize {
+		1
+	}
+
+	fn peek(&self, _: usize) -> i64 {
+		(self.rng.clone().next_u64() >> 1) as i64
+	}
+
+	fn read(&mut self, _: usize) -> i64 {
+		(self.rng.next_u64() >> 1) as i64
+	}
+
+	fn write(&mut self, _: usize, seed: i64) {
+		self.rng = Rng::new(seed as u64);
+	}
+}
+
+/// A grid of pixels stored row by row, one word each.
+pub struct Framebuffer {
+	width: usize,
+	height: usize,
+	pixels: Vec<i64>,
+}
+
+impl Framebuffer {
+	/// Panics if either dimension is zero, or if there are more pixels than
+	/// fit in a `usize`.
+	pub fn new(width: usize, height: usize) -> Self {
+		assert!(width > 0 && height > 0, "a framebuffer cannot be empty");
+		let size = width
+			.checked_mul(height)
+			.expect("a framebuffer cannot have that many pixels");
+		Framebuffer {
+			width,
+			height,
+			pixels: vec![0; size],
+		}
+	}
+
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	pub fn height(&self) -> usize {
+		self.height
+	}
+
+	pub fn pixel(&self, x: usize, y: usize) -> i64 {
+		self.pixels[y * self.width + x]
+	}
+
+	/// Draws the screen as text: zero is blank, a printable ASCII code is
+	/// that character and anything else is `#`.
+	pub fn render(&self) -> String {
+		let mut out = String::with_capacity((self.width + 1) * self.height);
+		for row in self.pixels.chunks(self.width) {
+			for &pixel in row {
+				out.push(match pixel {
+					0 => ' ',
+					32..=126 => pixel as u8 as char,
+					_ => '#',
+				});
+			}
+			out.push('\n');
+		}
+		out
+	}
+}
+
+impl Device for Framebuffer {
+	fn size(&self) -> usize {
+		self.pixels.len()
+	}
+
+	fn peek(&self, offset: usize) -> i64 {
+		self.pixels[offset]
+	}
+
+	fn write(&mut self, offset: usize, value: i64) {
+		self.pixels[offset] = value;
+	}
+}
+
+/// A character terminal. Writing an ASCII code prints it, and reading takes
+/// the next key typed, or 0 if none is waiting. The second address reads as
+/// the number of keys waiting.
+#[derive(Default)]
+pub struct Console {
+	keys: VecDeque<i64>,
+	text: String,
+	echo: bool,
+}
+
+impl Console {
+	/// A console that keeps what is printed for `text` to return.
+	pub fn new() -> Self {
+		Console::default()
+	}
+
+	/// A console that also prints to standard output as the program runs.
+	pub fn echoing() -> Self {
+		Console {
+			echo: true,
+			..Console::default()
+		}
+	}
+
+	/// Queues keys for the program to read.
+	pub fn type_keys(&mut self, keys: &str) {
+		self.keys.extend(keys.bytes().map(i64::from));
+	}
+
+	/// Everything printed so far.
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+}
+
+impl Device for Console {
+	fn size(&self) -> usize {
+		2
+	}
+
+	fn peek(&self, offset: usize) -> i64 {
+		match offset {
+			0 => self.keys.front().copied().unwrap_or(0),
+			_ => self.keys.len() as i64,
+		}
+	}
+
+	fn read(&mut self, offset: usize) -> i64 {
+		match offset {
+			0 => self.keys.pop_front().unwrap_or(0),
+			_ => self.peek(offset),
+		}
+	}
+
+	fn write(&mut self, offset: usize, value: i64) {
+		if offset != 0 || !(0..=127).contains(&value) {
+			return;
+		}
+		let c = value as u8 as char;
+		self.text.push(c);
+		if self.echo {
+			let stdout = io::stdout();
+			let mut stdout = stdout.lock();
+			let _ = write!(stdout, "{}", c);
+			let _ = stdout.flush();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn random_reseeds_to_the_same_sequence() {
+		let mut random = Random::new(7);
+		let first: Vec<i64> = (0..3).map(|_| random.read(0)).collect();
+		random.write(0, 7);
+		assert_eq!(random.peek(0), first[0]);
+		let again: Vec<i64> = (0..3).map(|_| random.read(0)).collect();
+		assert_eq!(again, first);
+		assert!(first.iter().all(|&value| value >= 0));
+	}
+
+	#[test]
+	#[should_panic(expected = "a framebuffer cannot be empty")]
+	fn framebuffer_rejects_zero_width() {
+		Framebuffer::new(0, 20);
+	}
+
+	#[test]
+	#[should_panic(expected = "a framebuffer cannot have that many pixels")]
+	fn framebuffer_rejects_too_many_pixels() {
+		Framebuffer::new(usize::MAX / 2, 3);
+	}
+}
@@ -0,0 +1,60 @@
+use super::Instruction;
+use std::fmt;
+
+/// One line of a listing: either a decoded instruction or a single word that
+/// could not be decoded and is shown as data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Line<'a> {
+	pub address: usize,
+	pub words: &'a [i64],
+	pub instruction: Option<Instruction>,
+}
+
+impl<'a> fmt::Display for Line<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let words: Vec<String> =
+			self.words.iter().map(|w| w.to_string()).collect();
+		write!(f, "{:>6}  {:<28} ", self.address, words.join(" "))?;
+		match self.instruction {
+			Some(instruction) => write!(f, "{}", instruction),
+			None => write!(f, "DB {}", self.words[0]),
+		}
+	}
+}
+
+/// Walks `memory` linearly from `address`, decoding one line at a time.
+pub struct Lines<'a> {
+	memory: &'a [i64],
+	address: usize,
+}
+
+impl<'a> Iterator for Lines<'a> {
+	type Item = Line<'a>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.address >= self.memory.len() {
+			return None;
+		}
+		let address = self.address;
+		let instruction = Instruction::decode(address, self.memory)
+			.ok()
+			.filter(|i| address + i.width() <= self.memory.len());
+		let width = instruction.map_or(1, |i| i.width());
+		self.address += width;
+		Some(Line {
+			address,
+			words: &self.memory[address..address + width],
+			instruction,
+		})
+	}
+}
+
+pub fn lines(memory: &[i64], address: usize) -> Lines<'_> {
+	Lines { memory, address }
+}
+
+/// Produces an annotated listing of the whole image, one line per
+/// instruction.
+pub fn disassemble(memory: &[i64]) -> String {
+	lines(memory, 0).map(|line| format!("{}\n", line)).collect()
+}
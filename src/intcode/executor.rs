@@ -0,0 +1,284 @@
+//! Running machines as futures on a small single-threaded executor, so that
+//! several of them can be wired together with channels and driven as plain
+//! sequential code. A machine waiting for input is a task waiting on its
+//! channel rather than a `State::AwaitingInput` to be handled by hand.
+//!
+//! ```
+//! use advent_of_code_2019::intcode::executor::{channel, Executor};
+//! use advent_of_code_2019::intcode::{State, Vm};
+//!
+//! let mut executor = Executor::new();
+//! let (to_a, mut a_in) = channel();
+//! let (to_b, mut b_in) = channel();
+//! // `a` adds one to what it reads, and `b` sends 1 and reads the answer.
+//! let a = executor.spawn(async move {
+//!     Vm::new(vec![3, 9, 1001, 9, 1, 9, 4, 9, 99, 0])
+//!         .run_async(&mut a_in, to_b)
+//!         .await
+//! });
+//! let b = executor.spawn(async move {
+//!     let mut vm = Vm::new(vec![104, 1, 3, 0, 99]);
+//!     let result = vm.run_async(&mut b_in, to_a).await;
+//!     (result, vm.memory()[0])
+//! });
+//! let stuck = executor.run();
+//! assert_eq!(stuck, 0);
+//! assert_eq!(a.take(), Some(Ok(State::Halted)));
+//! assert_eq!(b.take(), Some((Ok(State::Halted), 2)));
+//! ```
+
+use super::io::Output;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// A source of input values that may have to wait for them.
+pub trait AsyncInput {
+	/// The next value, or `None` once no more values will ever come.
+	fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<Option<i64>>;
+}
+
+impl<T: AsyncInput + ?Sized> AsyncInput for &mut T {
+	fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<Option<i64>> {
+		(**self).poll_read(cx)
+	}
+}
+
+/// The future returned by `read`.
+pub struct Read<'a, I: ?Sized>(&'a mut I);
+
+/// Waits for the next value from `input`.
+pub fn read<I: AsyncInput + ?Sized>(input: &mut I) -> Read<'_, I> {
+	Read(input)
+}
+
+impl<I: AsyncInput + ?Sized> Future for Read<'_, I> {
+	type Output = Option<i64>;
+
+	fn poll(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+	) -> Poll<Self::Output> {
+		self.0.poll_read(cx)
+	}
+}
+
+/// The future returned by `yield_now`.
+pub struct YieldNow(bool);
+
+/// Lets other tasks run before this one carries on.
+pub fn yield_now() -> YieldNow {
+	YieldNow(false)
+}
+
+impl Future for YieldNow {
+	type Output = ();
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+		if self.0 {
+			return Poll::Ready(());
+		}
+		self.0 = true;
+		cx.waker().wake_by_ref();
+		Poll::Pending
+	}
+}
+
+#[derive(Default)]
+struct Channel {
+	queue: VecDeque<i64>,
+	senders: usize,
+	waker: Option<Waker>,
+}
+
+impl Channel {
+	fn wake(&mut self) {
+		if let Some(waker) = self.waker.take() {
+			waker.wake();
+		}
+	}
+}
+
+/// The sending half of a channel. Sending never waits, and a machine can
+/// write its output straight into one.
+pub struct Sender(Rc<RefCell<Channel>>);
+
+/// The receiving half of a channel, which a machine can read its input from.
+pub struct Receiver(Rc<RefCell<Channel>>);
+
+/// An unbounded channel of values for tasks on the same executor.
+pub fn channel() -> (Sender, Receiver) {
+	let channel = Rc::new(RefCell::new(Channel {
+		senders: 1,
+		..Channel::default()
+	}));
+	(Sender(channel.clone()), Receiver(channel))
+}
+
+impl Sender {
+	pub fn send(&self, value: i64) {
+		let mut channel = self.0.borrow_mut();
+		channel.queue.push_back(value);
+		channel.wake();
+	}
+}
+
+impl Clone for Sender {
+	fn clone(&self) -> Self {
+		self.0.borrow_mut().senders += 1;
+		Sender(self.0.clone())
+	}
+}
+
+/// Once every sender is gone the receiver reads `None`, so wake it to see.
+impl Drop for Sender {
+	fn drop(&mut self) {
+		let mut channel = self.0.borrow_mut();
+		channel.senders -= 1;
+		if channel.senders == 0 {
+			channel.wake();
+		}
+	}
+}
+
+impl Output for Sender {
+	fn write(&mut self, value: i64) {
+		self.send(value);
+	}
+}
+
+impl Receiver {
+	/// Waits for the next value, or `None` once every sender is gone.
+	pub fn recv(&mut self) -> Read<'_, Self> {
+		read(self)
+	}
+
+	/// The next value if one is waiting.
+	pub fn try_recv(&mut self) -> Option<i64> {
+		self.0.borrow_mut().queue.pop_front()
+	}
+}
+
+impl AsyncInput for Receiver {
+	fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<Option<i64>> {
+		let mut channel = self.0.borrow_mut();
+		if let Some(value) = channel.queue.pop_front() {
+			Poll::Ready(Some(value))
+		} else if channel.senders == 0 {
+			Poll::Ready(None)
+		} else {
+			channel.waker = Some(cx.waker().clone());
+			Poll::Pending
+		}
+	}
+}
+
+struct Slot<T> {
+	value: Option<T>,
+	waker: Option<Waker>,
+}
+
+/// The result of a spawned task, which can be awaited from another task or
+/// taken once the executor has run.
+pub struct JoinHandle<T>(Rc<RefCell<Slot<T>>>);
+
+impl<T> JoinHandle<T> {
+	/// The task's result, if it has finished and nothing took it yet.
+	pub fn take(&self) -> Option<T> {
+		self.0.borrow_mut().value.take()
+	}
+}
+
+impl<T> Future for JoinHandle<T> {
+	type Output = T;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+		let mut slot = self.0.borrow_mut();
+		match slot.value.take() {
+			Some(value) => Poll::Ready(value),
+			None => {
+				slot.waker = Some(cx.waker().clone());
+				Poll::Pending
+			}
+		}
+	}
+}
+
+struct TaskWaker {
+	id: usize,
+	ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl Wake for TaskWaker {
+	fn wake(self: Arc<Self>) {
+		self.ready.lock().unwrap().push_back(self.id);
+	}
+}
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Polls spawned tasks on the current thread, in the order they are woken.
+#[derive(Default)]
+pub struct Executor {
+	tasks: Vec<Option<Task>>,
+	ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl Executor {
+	pub fn new() -> Self {
+		Executor::default()
+	}
+
+	pub fn spawn<F>(&mut self, future: F) -> JoinHandle<F::Output>
+	where
+		F: Future + 'static,
+	{
+		let slot = Rc::new(RefCell::new(Slot {
+			value: None,
+			waker: None,
+		}));
+		let result = slot.clone();
+		let id = self.tasks.len();
+		self.tasks.push(Some(Box::pin(async move {
+			let value = future.await;
+			let mut slot = result.borrow_mut();
+			slot.value = Some(value);
+			if let Some(waker) = slot.waker.take() {
+				waker.wake();
+			}
+		})));
+		self.ready.lock().unwrap().push_back(id);
+		JoinHandle(slot)
+	}
+
+	/// Runs tasks until none can make progress. Returns how many are left
+	/// unfinished, all waiting for something that will never happen.
+	pub fn run(&mut self) -> usize {
+		loop {
+			let id = match self.ready.lock().unwrap().pop_front() {
+				Some(id) => id,
+				None => break,
+			};
+			let task = match &mut self.tasks[id] {
+				Some(task) => task,
+				None => continue,
+			};
+			let waker = Waker::from(Arc::new(TaskWaker {
+				id,
+				ready: self.ready.clone(),
+			}));
+			if task
+				.as_mut()
+				.poll(&mut Context::from_waker(&waker))
+				.is_ready()
+			{
+				self.tasks[id] = None;
+			}
+		}
+		self.tasks.iter().filter(|task| task.is_some()).count()
+	}
+}
@@ -0,0 +1,95 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+	Read,
+	Write,
+}
+
+/// Why a machine could not execute the instruction at `pointer`, whose first
+/// word is `word`. The machine is left as it was before the instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+	UnknownOpcode {
+		pointer: usize,
+		word: i64,
+	},
+	BadMode {
+		pointer: usize,
+		word: i64,
+		mode: i64,
+	},
+	OutOfBounds {
+		pointer: usize,
+		word: i64,
+		address: usize,
+		access: Access,
+	},
+	NegativeAddress {
+		pointer: usize,
+		word: i64,
+		address: i64,
+	},
+	ImmediateWrite {
+		pointer: usize,
+		word: i64,
+	},
+	Overflow {
+		pointer: usize,
+		word: i64,
+	},
+}
+
+impl Fault {
+	pub fn pointer(&self) -> usize {
+		match *self {
+			Fault::UnknownOpcode { pointer, .. }
+			| Fault::BadMode { pointer, .. }
+			| Fault::OutOfBounds { pointer, .. }
+			| Fault::NegativeAddress { pointer, .. }
+			| Fault::ImmediateWrite { pointer, .. }
+			| Fault::Overflow { pointer, .. } => pointer,
+		}
+	}
+
+	pub fn word(&self) -> i64 {
+		match *self {
+			Fault::UnknownOpcode { word, .. }
+			| Fault::BadMode { word, .. }
+			| Fault::OutOfBounds { word, .. }
+			| Fault::NegativeAddress { word, .. }
+			| Fault::ImmediateWrite { word, .. }
+			| Fault::Overflow { word, .. } => word,
+		}
+	}
+}
+
+impl fmt::Display for Fault {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Fault::UnknownOpcode { .. } => write!(f, "unknown opcode")?,
+			Fault::BadMode { mode, .. } => {
+				write!(f, "unsupported parameter mode {}", mode)?
+			}
+			Fault::OutOfBounds {
+				address, access, ..
+			} => {
+				let access = match access {
+					Access::Read => "read",
+					Access::Write => "write",
+				};
+				write!(f, "out-of-bounds {} at address {}", access, address)?
+			}
+			Fault::NegativeAddress { address, .. } => {
+				write!(f, "negative address {}", address)?
+			}
+			Fault::ImmediateWrite { .. } => {
+				write!(f, "write through an immediate-mode parameter")?
+			}
+			Fault::Overflow { .. } => write!(f, "arithmetic overflow")?,
+		}
+		write!(f, " (instruction {} at {})", self.word(), self.pointer())
+	}
+}
+
+impl std::error::Error for Fault {}
@@ -0,0 +1,472 @@
+//! Differential fuzzing of the machine.
+//!
+//! Days 2, 5 and 7 once each had their own copy of the interpreter. They now
+//! all run on `Vm`, so what is compared here is every way of driving a `Vm`
+//! against a small reference interpreter written separately from it. Random
+//! programs are generated within the feature set of each day, and any program
+//! on which the engines disagree is shrunk to a minimal failing case.
+
+pub use super::rng::Rng;
+use super::trace::{self, Tracer};
+use super::{io, State, Vm};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Every engine stops after this many instructions.
+pub const STEPS: u64 = 1_000;
+/// The memory limit every engine runs with, kept small so that a program
+/// writing far past its end stays cheap to run and to shrink.
+pub const MEMORY_LIMIT: usize = 1 << 12;
+
+/// The instructions and parameter modes a generated program may use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Features {
+	/// Add, multiply and halt in position mode, as on day 2.
+	Day2,
+	/// Opcodes 1 to 8 and halt in position and immediate mode, as on days 5
+	/// and 7.
+	Day5,
+	/// Day 5's instructions with relative mode parameters as well.
+	AllModes,
+}
+
+impl Features {
+	pub const ALL: [Features; 3] =
+		[Features::Day2, Features::Day5, Features::AllModes];
+
+	fn opcodes(self) -> &'static [i64] {
+		match self {
+			Features::Day2 => &[1, 2],
+			_ => &[1, 2, 3, 4, 5, 6, 7, 8],
+		}
+	}
+
+	fn modes(self) -> &'static [i64] {
+		match self {
+			Features::Day2 => &[0],
+			Features::Day5 => &[0, 1],
+			Features::AllModes => &[0, 1, 2],
+		}
+	}
+}
+
+/// A program and the input it is given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Case {
+	pub program: Vec<i64>,
+	pub input: Vec<i64>,
+}
+
+fn width(opcode: i64) -> usize {
+	match opcode {
+		1 | 2 | 7 | 8 => 4,
+		5 | 6 => 3,
+		3 | 4 | 9 => 2,
+		_ => 1,
+	}
+}
+
+/// Generates a well-formed program: a run of instructions ending in a halt,
+/// followed by some data. Addresses point into the program, destinations are
+/// never immediate, and jump targets are usually the start of an instruction.
+pub fn generate(rng: &mut Rng, features: Features) -> Case {
+	let opcodes: Vec<i64> = (0..1 + rng.below(12))
+		.map(|_| features.opcodes()[rng.below(features.opcodes().len())])
+		.collect();
+	let mut starts = Vec::with_capacity(opcodes.len() + 1);
+	let mut code = 0;
+	for &opcode in &opcodes {
+		starts.push(code);
+		code += width(opcode);
+	}
+	starts.push(code);
+	let len = code + 1 + 1 + rng.below(8);
+
+	let mut program = Vec::with_capacity(len);
+	for &opcode in &opcodes {
+		let at = program.len();
+		program.push(opcode);
+		let destination = match opcode {
+			1 | 2 | 7 | 8 => Some(3),
+			3 => Some(1),
+			_ => None,
+		};
+		for n in 1..width(opcode) {
+			let mut mode = features.modes()[rng.below(features.modes().len())];
+			if destination == Some(n) && mode == 1 {
+				mode = 0;
+			}
+			program[at] += mode * 10_i64.pow(n as u32 + 1);
+			let jump_target = (opcode == 5 || opcode == 6) && n == 2;
+			program.push(match mode {
+				1 if jump_target && !rng.one_in(8) => {
+					starts[rng.below(starts.len())] as i64
+				}
+				1 if rng.one_in(10) => rng.between(-(1 << 40), 1 << 40),
+				1 => rng.between(-10, 10),
+				_ => rng.below(len) as i64,
+			});
+		}
+	}
+	program.push(99);
+	while program.len() < len {
+		program.push(if rng.one_in(2) {
+			starts[rng.below(starts.len())] as i64
+		} else {
+			rng.between(-10, 10)
+		});
+	}
+	let input = (0..rng.below(4)).map(|_| rng.between(-5, 5)).collect();
+	Case { program, input }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ending {
+	Halted,
+	AwaitingInput,
+	OutOfSteps,
+	Fault,
+}
+
+/// Everything observable about how a program ran.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+	pub ending: Ending,
+	pub pointer: usize,
+	pub steps: u64,
+	pub outputs: Vec<i64>,
+	pub memory: Vec<i64>,
+}
+
+impl fmt::Display for Outcome {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{:?} at {} after {} steps, outputs {:?}, memory {:?}",
+			self.ending, self.pointer, self.steps, self.outputs, self.memory
+		)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Engine {
+	/// The interpreter in this module.
+	Reference,
+	/// `Vm::run_with` with the decoded-instruction cache on.
+	Cached,
+	/// `Vm::run_with` with the cache off.
+	Uncached,
+	/// `Vm::step_with`, one instruction per call.
+	Stepped,
+	/// A `Tracer` driving `Vm::step`.
+	Traced,
+}
+
+impl Engine {
+	pub const ALL: [Engine; 5] = [
+		Engine::Reference,
+		Engine::Cached,
+		Engine::Uncached,
+		Engine::Stepped,
+		Engine::Traced,
+	];
+
+	pub fn run(self, case: &Case) -> Outcome {
+		let mut vm = Vm::new(case.program.clone());
+		vm.set_budget(Some(STEPS));
+		vm.set_memory_limit(MEMORY_LIMIT);
+		let mut outputs = Vec::new();
+		let result = match self {
+			Engine::Reference => return reference(case),
+			Engine::Cached => {
+				vm.run_with(io::iter(case.input.clone()), &mut outputs)
+			}
+			Engine::Uncached => {
+				vm.set_decode_cache(false);
+				vm.run_with(io::iter(case.input.clone()), &mut outputs)
+			}
+			Engine::Stepped => {
+				let mut input = io::iter(case.input.clone());
+				loop {
+					match vm.step_with(&mut input, &mut outputs) {
+						Ok(None) => {}
+						Ok(Some(state)) => break Ok(state),
+						Err(fault) => break Err(fault),
+					}
+				}
+			}
+			Engine::Traced => {
+				for &value in &case.input {
+					vm.push_input(value);
+				}
+				let mut tracer =
+					Tracer::new(std::io::sink(), trace::Format::JsonLines);
+				let result = tracer.run(&mut vm);
+				outputs = vm.read_output();
+				match result {
+					Ok(state) => Ok(state),
+					Err(trace::Error::Fault(fault)) => Err(fault),
+					Err(trace::Error::Io(e)) => panic!("{}", e),
+				}
+			}
+		};
+		Outcome {
+			ending: match result {
+				Ok(State::Halted) => Ending::Halted,
+				Ok(State::AwaitingInput) => Ending::AwaitingInput,
+				Ok(State::BudgetExhausted { .. }) => Ending::OutOfSteps,
+				Ok(State::InfiniteLoop { .. }) => {
+					unreachable!("loop detection is off")
+				}
+				Err(_) => Ending::Fault,
+			},
+			pointer: vm.pointer(),
+			steps: vm.steps(),
+			outputs,
+			memory: vm.memory().to_vec(),
+		}
+	}
+}
+
+/// A straightforward interpreter, deliberately sharing no code with `Vm`.
+/// Opcode 9 is never generated, but a program can still write one.
+struct Reference {
+	memory: Vec<i64>,
+	pointer: usize,
+	relative_base: i64,
+}
+
+impl Reference {
+	fn get(&self, address: usize) -> i64 {
+		self.memory.get(address).copied().unwrap_or(0)
+	}
+
+	/// The mode of parameter `n`, counting from 1.
+	fn mode(&self, n: usize) -> i64 {
+		self.get(self.pointer) / 10_i64.pow(n as u32 + 1) % 10
+	}
+
+	fn address(&self, n: usize) -> Option<usize> {
+		let raw = self.get(self.pointer + n);
+		let address = match self.mode(n) {
+			0 => raw,
+			2 => self.relative_base.checked_add(raw)?,
+			_ => return None,
+		};
+		usize::try_from(address)
+			.ok()
+			.filter(|&address| address < MEMORY_LIMIT)
+	}
+
+	fn value(&self, n: usize) -> Option<i64> {
+		match self.mode(n) {
+			1 => Some(self.get(self.pointer + n)),
+			_ => self.address(n).map(|address| self.get(address)),
+		}
+	}
+
+	fn set(&mut self, address: usize, value: i64) {
+		if address >= self.memory.len() {
+			self.memory.resize(address + 1, 0);
+		}
+		self.memory[address] = value;
+	}
+
+	/// Executes one instruction, returning how the program ended if it did.
+	/// `None` inside the result means the instruction ran.
+	fn step(
+		&mut self,
+		input: &mut impl Iterator<Item = i64>,
+		outputs: &mut Vec<i64>,
+	) -> Option<Option<Ending>> {
+		let opcode = self.get(self.pointer) % 100;
+		if !matches!(opcode, 1..=9 | 99) {
+			return None;
+		}
+		if (1..width(opcode)).any(|n| self.mode(n) > 2) {
+			return None;
+		}
+		let mut next = self.pointer + width(opcode);
+		match opcode {
+			1 | 2 | 7 | 8 => {
+				let (a, b) = (self.value(1)?, self.value(2)?);
+				let value = match opcode {
+					1 => a.checked_add(b)?,
+					2 => a.checked_mul(b)?,
+					7 => (a < b) as i64,
+					_ => (a == b) as i64,
+				};
+				let c = self.address(3)?;
+				self.set(c, value);
+			}
+			3 => {
+				let a = self.address(1)?;
+				match input.next() {
+					Some(value) => self.set(a, value),
+					None => return Some(Some(Ending::AwaitingInput)),
+				}
+			}
+			4 => outputs.push(self.value(1)?),
+			5 | 6 => {
+				if (self.value(1)? != 0) == (opcode == 5) {
+					next = usize::try_from(self.value(2)?).ok()?;
+				}
+			}
+			9 => {
+				self.relative_base =
+					self.relative_base.checked_add(self.value(1)?)?
+			}
+			_ => return Some(Some(Ending::Halted)),
+		}
+		self.pointer = next;
+		Some(None)
+	}
+}
+
+fn reference(case: &Case) -> Outcome {
+	let mut machine = Reference {
+		memory: case.program.clone(),
+		pointer: 0,
+		relative_base: 0,
+	};
+	let mut input = case.input.iter().copied();
+	let mut outputs = Vec::new();
+	let mut steps = 0;
+	let ending = loop {
+		if steps == STEPS {
+			break Ending::OutOfSteps;
+		}
+		match machine.step(&mut input, &mut outputs) {
+			None => break Ending::Fault,
+			Some(Some(Ending::AwaitingInput)) => break Ending::AwaitingInput,
+			Some(Some(ending)) => {
+				steps += 1;
+				break ending;
+			}
+			Some(None) => steps += 1,
+		}
+	};
+	Outcome {
+		ending,
+		pointer: machine.pointer,
+		steps,
+		outputs,
+		memory: machine.memory,
+	}
+}
+
+/// Runs `case` on every engine, returning all their outcomes if any of them
+/// disagrees with the reference.
+pub fn check(case: &Case) -> Option<Vec<(Engine, Outcome)>> {
+	let outcomes: Vec<(Engine, Outcome)> =
+		Engine::ALL.iter().map(|&e| (e, e.run(case))).collect();
+	if outcomes.iter().all(|(_, o)| *o == outcomes[0].1) {
+		None
+	} else {
+		Some(outcomes)
+	}
+}
+
+/// Makes a failing case as small as it can while `fails` still holds, by
+/// removing runs of words and input values and moving words towards zero.
+pub fn shrink(case: &Case, fails: impl Fn(&Case) -> bool) -> Case {
+	let mut case = case.clone();
+	loop {
+		let mut progress = false;
+
+		let mut size = case.program.len() / 2;
+		while size > 0 {
+			let mut start = 0;
+			while start + size <= case.program.len() {
+				let mut smaller = case.clone();
+				smaller.program.drain(start..start + size);
+				if !smaller.program.is_empty() && fails(&smaller) {
+					case = smaller;
+					progress = true;
+				} else {
+					start += size;
+				}
+			}
+			size /= 2;
+		}
+
+		for i in 0..case.program.len() {
+			let word = case.program[i];
+			for &simpler in &[0, word / 2, word - word.signum()] {
+				if simpler == case.program[i] {
+					continue;
+				}
+				let mut smaller = case.clone();
+				smaller.program[i] = simpler;
+				if fails(&smaller) {
+					case = smaller;
+					progress = true;
+					break;
+				}
+			}
+		}
+
+		let mut i = 0;
+		while i < case.input.len() {
+			let mut smaller = case.clone();
+			smaller.input.remove(i);
+			if fails(&smaller) {
+				case = smaller;
+				progress = true;
+			} else {
+				i += 1;
+			}
+		}
+
+		if !progress {
+			return case;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn engines_agree_on_generated_programs() {
+		let mut rng = Rng::new(1);
+		for _ in 0..500 {
+			for &features in &Features::ALL {
+				let case = generate(&mut rng, features);
+				if check(&case).is_some() {
+					let case = shrink(&case, |c| check(c).is_some());
+					panic!("the engines disagree on {:?}", case);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn engines_agree_when_the_budget_ends_on_an_undecodable_word() {
+		// Counts down for exactly the budget, leaving the pointer on a zero.
+		let program = vec![1001, 8, -1, 8, 1005, 8, 0, 0, STEPS as i64 / 2];
+		let case = Case {
+			program,
+			input: vec![],
+		};
+		let outcome = Engine::Reference.run(&case);
+		assert_eq!((outcome.ending, outcome.pointer), (Ending::OutOfSteps, 7));
+		assert_eq!(check(&case), None);
+	}
+
+	#[test]
+	fn shrink_keeps_the_case_failing() {
+		let prints_seven =
+			|case: &Case| Engine::Reference.run(case).outputs.contains(&7);
+		let case = Case {
+			program: vec![3, 11, 1101, 3, 4, 12, 4, 12, 4, 11, 99, 0, 0],
+			input: vec![5, 6],
+		};
+		assert!(prints_seven(&case));
+		let shrunk = shrink(&case, prints_seven);
+		assert!(prints_seven(&shrunk));
+		assert!(shrunk.program.len() < case.program.len());
+		assert!(shrunk.input.is_empty());
+	}
+}
@@ -0,0 +1,223 @@
+use super::Fault;
+use std::convert::TryFrom;
+use std::fmt;
+
+const ADD_OP: i64 = 1;
+const MULT_OP: i64 = 2;
+const IN_OP: i64 = 3;
+const OUT_OP: i64 = 4;
+const JIT_OP: i64 = 5;
+const JIF_OP: i64 = 6;
+const LT_OP: i64 = 7;
+const EQ_OP: i64 = 8;
+const ARB_OP: i64 = 9;
+const HALT_OP: i64 = 99;
+const POSITION_MODE: i64 = 0;
+const IMMEDIATE_MODE: i64 = 1;
+const RELATIVE_MODE: i64 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+	Position,
+	Immediate,
+	Relative,
+}
+
+impl TryFrom<i64> for Mode {
+	type Error = &'static str;
+	fn try_from(value: i64) -> Result<Self, Self::Error> {
+		match value {
+			POSITION_MODE => Ok(Mode::Position),
+			IMMEDIATE_MODE => Ok(Mode::Immediate),
+			RELATIVE_MODE => Ok(Mode::Relative),
+			_ => Err("unsupported parameter mode"),
+		}
+	}
+}
+
+/// A parameter as it appears in memory, before it is resolved against the
+/// current state of the machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Parameter {
+	pub mode: Mode,
+	pub value: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+	Add(Parameter, Parameter, Parameter),
+	Multiply(Parameter, Parameter, Parameter),
+	Input(Parameter),
+	Output(Parameter),
+	JumpIfTrue(Parameter, Parameter),
+	JumpIfFalse(Parameter, Parameter),
+	LessThan(Parameter, Parameter, Parameter),
+	Equals(Parameter, Parameter, Parameter),
+	AdjustRelativeBase(Parameter),
+	Halt,
+}
+
+impl Instruction {
+	/// Decodes the instruction starting at `pointer`. Words past the end of
+	/// `memory` are read as zero.
+	pub fn decode(
+		pointer: usize,
+		memory: &[i64],
+	) -> Result<Instruction, Fault> {
+		let word = |i: usize| memory.get(i).copied().unwrap_or(0);
+		let parameter = |n: usize| -> Result<Parameter, Fault> {
+			let mode = (word(pointer) / 10_i64.pow(n as u32 + 2)) % 10;
+			Ok(Parameter {
+				mode: Mode::try_from(mode).map_err(|_| Fault::BadMode {
+					pointer,
+					word: word(pointer),
+					mode,
+				})?,
+				value: word(pointer + n + 1),
+			})
+		};
+		Ok(match word(pointer) % 100 {
+			ADD_OP => {
+				Instruction::Add(parameter(0)?, parameter(1)?, parameter(2)?)
+			}
+			MULT_OP => Instruction::Multiply(
+				parameter(0)?,
+				parameter(1)?,
+				parameter(2)?,
+			),
+			IN_OP => Instruction::Input(parameter(0)?),
+			OUT_OP => Instruction::Output(parameter(0)?),
+			JIT_OP => Instruction::JumpIfTrue(parameter(0)?, parameter(1)?),
+			JIF_OP => Instruction::JumpIfFalse(parameter(0)?, parameter(1)?),
+			LT_OP => Instruction::LessThan(
+				parameter(0)?,
+				parameter(1)?,
+				parameter(2)?,
+			),
+			EQ_OP => {
+				Instruction::Equals(parameter(0)?, parameter(1)?, parameter(2)?)
+			}
+			ARB_OP => Instruction::AdjustRelativeBase(parameter(0)?),
+			HALT_OP => Instruction::Halt,
+			_ => {
+				return Err(Fault::UnknownOpcode {
+					pointer,
+					word: word(pointer),
+				})
+			}
+		})
+	}
+
+	/// Encodes the instruction back into the words `decode` reads.
+	pub fn encode(&self) -> Vec<i64> {
+		let parameters = self.parameters();
+		let mut opcode = self.opcode();
+		let mut words = Vec::with_capacity(self.width());
+		for (n, parameter) in parameters.iter().enumerate() {
+			let mode = match parameter.mode {
+				Mode::Position => POSITION_MODE,
+				Mode::Immediate => IMMEDIATE_MODE,
+				Mode::Relative => RELATIVE_MODE,
+			};
+			opcode += mode * 10_i64.pow(n as u32 + 2);
+		}
+		words.push(opcode);
+		words.extend(parameters.iter().map(|p| p.value));
+		words
+	}
+
+	fn opcode(&self) -> i64 {
+		match self {
+			Instruction::Add(..) => ADD_OP,
+			Instruction::Multiply(..) => MULT_OP,
+			Instruction::Input(..) => IN_OP,
+			Instruction::Output(..) => OUT_OP,
+			Instruction::JumpIfTrue(..) => JIT_OP,
+			Instruction::JumpIfFalse(..) => JIF_OP,
+			Instruction::LessThan(..) => LT_OP,
+			Instruction::Equals(..) => EQ_OP,
+			Instruction::AdjustRelativeBase(..) => ARB_OP,
+			Instruction::Halt => HALT_OP,
+		}
+	}
+
+	/// The parameter the instruction writes its result through, if any.
+	pub fn destination(&self) -> Option<Parameter> {
+		match *self {
+			Instruction::Add(_, _, c)
+			| Instruction::Multiply(_, _, c)
+			| Instruction::LessThan(_, _, c)
+			| Instruction::Equals(_, _, c) => Some(c),
+			Instruction::Input(a) => Some(a),
+			_ => None,
+		}
+	}
+
+	pub fn mnemonic(&self) -> &'static str {
+		match self {
+			Instruction::Add(..) => "ADD",
+			Instruction::Multiply(..) => "MUL",
+			Instruction::Input(..) => "IN",
+			Instruction::Output(..) => "OUT",
+			Instruction::JumpIfTrue(..) => "JT",
+			Instruction::JumpIfFalse(..) => "JF",
+			Instruction::LessThan(..) => "LT",
+			Instruction::Equals(..) => "EQ",
+			Instruction::AdjustRelativeBase(..) => "ARB",
+			Instruction::Halt => "HLT",
+		}
+	}
+
+	pub fn parameters(&self) -> Vec<Parameter> {
+		match *self {
+			Instruction::Add(a, b, c)
+			| Instruction::Multiply(a, b, c)
+			| Instruction::LessThan(a, b, c)
+			| Instruction::Equals(a, b, c) => vec![a, b, c],
+			Instruction::JumpIfTrue(a, b) | Instruction::JumpIfFalse(a, b) => {
+				vec![a, b]
+			}
+			Instruction::Input(a)
+			| Instruction::Output(a)
+			| Instruction::AdjustRelativeBase(a) => vec![a],
+			Instruction::Halt => vec![],
+		}
+	}
+
+	/// The number of words the instruction occupies, opcode included.
+	pub fn width(&self) -> usize {
+		match self {
+			Instruction::Add(..)
+			| Instruction::Multiply(..)
+			| Instruction::LessThan(..)
+			| Instruction::Equals(..) => 4,
+			Instruction::JumpIfTrue(..) | Instruction::JumpIfFalse(..) => 3,
+			Instruction::Input(..)
+			| Instruction::Output(..)
+			| Instruction::AdjustRelativeBase(..) => 2,
+			Instruction::Halt => 1,
+		}
+	}
+}
+
+impl fmt::Display for Parameter {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.mode {
+			Mode::Position => write!(f, "[{}]", self.value),
+			Mode::Immediate => write!(f, "#{}", self.value),
+			Mode::Relative if self.value < 0 => write!(f, "rb{}", self.value),
+			Mode::Relative => write!(f, "rb+{}", self.value),
+		}
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.mnemonic())?;
+		for (i, parameter) in self.parameters().iter().enumerate() {
+			let separator = if i == 0 { " " } else { ", " };
+			write!(f, "{}{}", separator, parameter)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,147 @@
+//! Sources of input and sinks of output a machine can be wired to.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc;
+
+pub trait Input {
+	/// The next input value, or `None` if there is none to give, in which
+	/// case the machine stops and waits.
+	fn read(&mut self) -> Option<i64>;
+}
+
+pub trait Output {
+	fn write(&mut self, value: i64);
+}
+
+impl<T: Input + ?Sized> Input for &mut T {
+	fn read(&mut self) -> Option<i64> {
+		(**self).read()
+	}
+}
+
+impl<T: Output + ?Sized> Output for &mut T {
+	fn write(&mut self, value: i64) {
+		(**self).write(value)
+	}
+}
+
+impl Input for VecDeque<i64> {
+	fn read(&mut self) -> Option<i64> {
+		self.pop_front()
+	}
+}
+
+impl Output for VecDeque<i64> {
+	fn write(&mut self, value: i64) {
+		self.push_back(value);
+	}
+}
+
+impl Output for Vec<i64> {
+	fn write(&mut self, value: i64) {
+		self.push(value);
+	}
+}
+
+/// Blocks until a value arrives, giving `None` once every sender is gone.
+impl Input for mpsc::Receiver<i64> {
+	fn read(&mut self) -> Option<i64> {
+		self.recv().ok()
+	}
+}
+
+/// Values sent after the receiver is gone are dropped.
+impl Output for mpsc::Sender<i64> {
+	fn write(&mut self, value: i64) {
+		let _ = self.send(value);
+	}
+}
+
+impl Output for mpsc::SyncSender<i64> {
+	fn write(&mut self, value: i64) {
+		let _ = self.send(value);
+	}
+}
+
+pub struct Iter<I>(I);
+
+/// Feeds the values of an iterator, in order.
+pub fn iter<I: IntoIterator<Item = i64>>(values: I) -> Iter<I::IntoIter> {
+	Iter(values.into_iter())
+}
+
+impl<I: Iterator<Item = i64>> Input for Iter<I> {
+	fn read(&mut self) -> Option<i64> {
+		self.0.next()
+	}
+}
+
+pub struct InputFn<F>(F);
+
+pub fn input_fn<F: FnMut() -> Option<i64>>(f: F) -> InputFn<F> {
+	InputFn(f)
+}
+
+impl<F: FnMut() -> Option<i64>> Input for InputFn<F> {
+	fn read(&mut self) -> Option<i64> {
+		(self.0)()
+	}
+}
+
+pub struct OutputFn<F>(F);
+
+pub fn output_fn<F: FnMut(i64)>(f: F) -> OutputFn<F> {
+	OutputFn(f)
+}
+
+impl<F: FnMut(i64)> Output for OutputFn<F> {
+	fn write(&mut self, value: i64) {
+		(self.0)(value)
+	}
+}
+
+/// Reads whitespace-separated integers from standard input. Tokens that are
+/// not integers are reported and skipped.
+#[derive(Default)]
+pub struct Stdin {
+	pending: VecDeque<i64>,
+}
+
+impl Stdin {
+	pub fn new() -> Self {
+		Stdin::default()
+	}
+}
+
+impl Input for Stdin {
+	fn read(&mut self) -> Option<i64> {
+		let stdin = io::stdin();
+		let mut stdin = stdin.lock();
+		while self.pending.is_empty() {
+			let mut line = String::new();
+			match stdin.read_line(&mut line) {
+				Ok(0) | Err(_) => return None,
+				Ok(_) => {}
+			}
+			for token in line.split_whitespace() {
+				match token.parse() {
+					Ok(value) => self.pending.push_back(value),
+					Err(_) => eprintln!("ignoring invalid input '{}'", token),
+				}
+			}
+		}
+		self.pending.pop_front()
+	}
+}
+
+/// Writes each value on its own line to standard output.
+#[derive(Default)]
+pub struct Stdout;
+
+impl Output for Stdout {
+	fn write(&mut self, value: i64) {
+		let stdout = io::stdout();
+		let _ = writeln!(stdout.lock(), "{}", value);
+	}
+}
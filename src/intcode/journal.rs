@@ -0,0 +1,126 @@
+//! An undo log of executed instructions, so a machine can run backwards.
+
+use std::collections::VecDeque;
+use std::mem;
+
+/// What one instruction changed, enough to put the machine back as it was.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Entry {
+	/// The step count before the instruction ran.
+	pub(super) step: u64,
+	pub(super) pointer: usize,
+	pub(super) relative_base: i64,
+	/// The length of memory before the instruction ran.
+	pub(super) length: usize,
+	/// Each address written with the value it held before, oldest first.
+	pub(super) writes: Vec<(usize, i64)>,
+	/// The value the instruction took from its input.
+	pub(super) input: Option<i64>,
+}
+
+impl Entry {
+	fn size(&self) -> usize {
+		mem::size_of::<Entry>()
+			+ self.writes.capacity() * mem::size_of::<(usize, i64)>()
+	}
+}
+
+/// The most recent instructions a machine executed, newest last. Once the
+/// entries take up more than the limit the oldest are forgotten.
+#[derive(Clone, Debug)]
+pub struct Journal {
+	entries: VecDeque<Entry>,
+	/// The instruction being executed, until it is retired.
+	pending: Entry,
+	bytes: usize,
+	limit: usize,
+}
+
+impl Journal {
+	pub(super) fn new(limit: usize) -> Self {
+		Journal {
+			entries: VecDeque::new(),
+			pending: Entry::default(),
+			bytes: 0,
+			limit,
+		}
+	}
+
+	pub(super) fn begin(
+		&mut self,
+		step: u64,
+		pointer: usize,
+		relative_base: i64,
+		length: usize,
+	) {
+		self.pending = Entry {
+			step,
+			pointer,
+			relative_base,
+			length,
+			writes: Vec::new(),
+			input: None,
+		};
+	}
+
+	pub(super) fn wrote(&mut self, address: usize, old: i64) {
+		self.pending.writes.push((address, old));
+	}
+
+	pub(super) fn read(&mut self, value: i64) {
+		self.pending.input = Some(value);
+	}
+
+	/// Keeps the pending entry now that its instruction has run.
+	pub(super) fn commit(&mut self) {
+		let entry = mem::take(&mut self.pending);
+		self.bytes += entry.size();
+		self.entries.push_back(entry);
+		while self.bytes > self.limit {
+			match self.entries.pop_front() {
+				Some(oldest) => self.bytes -= oldest.size(),
+				None => break,
+			}
+		}
+	}
+
+	pub(super) fn pop(&mut self) -> Option<Entry> {
+		let entry = self.entries.pop_back()?;
+		self.bytes -= entry.size();
+		Some(entry)
+	}
+
+	/// The number of instructions that can be undone.
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	/// Roughly how many bytes the entries take up.
+	pub fn bytes(&self) -> usize {
+		self.bytes
+	}
+
+	pub fn limit(&self) -> usize {
+		self.limit
+	}
+
+	/// The earliest step count the machine can be taken back to.
+	pub fn first_step(&self) -> Option<u64> {
+		self.entries.front().map(|entry| entry.step)
+	}
+
+	/// The step count just before the most recent instruction in the journal
+	/// that wrote to `address`, so that going back to it leaves that
+	/// instruction under the pointer.
+	pub fn last_write(&self, address: usize) -> Option<u64> {
+		self.entries
+			.iter()
+			.rev()
+			.find(|entry| entry.writes.iter().any(|&(a, _)| a == address))
+			.map(|entry| entry.step)
+	}
+}
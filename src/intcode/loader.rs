@@ -0,0 +1,93 @@
+use std::fmt;
+
+/// Where and why a program failed to load.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+	/// Byte offset of the bad field in the input.
+	pub offset: usize,
+	/// Index of the bad field, counting from 0.
+	pub field: usize,
+	/// The field's text, without surrounding whitespace and comments.
+	pub token: String,
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "field {} at byte {}: ", self.field, self.offset)?;
+		if self.token.is_empty() {
+			write!(f, "empty field")
+		} else {
+			write!(f, "invalid value '{}'", self.token)
+		}
+	}
+}
+
+impl std::error::Error for ParseError {}
+
+/// A field being read: its text so far and the offset it starts at.
+#[derive(Default)]
+struct Field {
+	token: String,
+	offset: Option<usize>,
+	/// Whitespace seen since the last character of the token.
+	gap: bool,
+}
+
+/// Parses a finished field onto the end of `words`. `end` is where the field
+/// stopped, for the error offset of an empty one.
+fn finish(
+	words: &mut Vec<i64>,
+	field: Field,
+	end: usize,
+) -> Result<(), ParseError> {
+	match field.token.parse() {
+		Ok(word) => {
+			words.push(word);
+			Ok(())
+		}
+		Err(_) => Err(ParseError {
+			offset: field.offset.unwrap_or(end),
+			field: words.len(),
+			token: field.token,
+		}),
+	}
+}
+
+/// Parses a comma-separated Intcode image. Whitespace, including newlines and
+/// carriage returns, may appear around any value, `#` starts a comment that
+/// runs to the end of the line, and a single trailing comma is allowed.
+pub fn parse(input: &str) -> Result<Vec<i64>, ParseError> {
+	let mut words = Vec::new();
+	let mut field = Field::default();
+	let mut in_comment = false;
+	for (offset, c) in input.char_indices() {
+		if in_comment {
+			in_comment = c != '\n';
+			continue;
+		}
+		match c {
+			'#' => {
+				in_comment = true;
+				field.gap = field.offset.is_some();
+			}
+			',' => {
+				finish(&mut words, std::mem::take(&mut field), offset)?;
+			}
+			c if c.is_whitespace() => field.gap = field.offset.is_some(),
+			c => {
+				if field.gap {
+					field.token.push(' ');
+					field.gap = false;
+				}
+				field.offset.get_or_insert(offset);
+				field.token.push(c);
+			}
+		}
+	}
+	// Only a field with something in it ends the program, so that a trailing
+	// comma is allowed.
+	if field.offset.is_some() {
+		finish(&mut words, field, input.len())?;
+	}
+	Ok(words)
+}
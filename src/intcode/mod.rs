@@ -0,0 +1,703 @@
+pub mod amplifier;
+pub mod ascii;
+pub mod asm;
+pub mod cfg;
+pub mod debugger;
+pub mod decompile;
+pub mod device;
+pub mod disasm;
+pub mod executor;
+mod fault;
+pub mod fuzz;
+mod instruction;
+pub mod io;
+pub mod journal;
+mod loader;
+pub mod network;
+pub mod optimize;
+pub mod profile;
+pub mod replay;
+pub mod rng;
+pub mod snapshot;
+pub mod trace;
+
+pub use fault::{Access, Fault};
+pub use instruction::{Instruction, Mode, Parameter};
+pub use loader::{parse, ParseError};
+
+use device::{Bus, Device};
+use executor::AsyncInput;
+use io::{Input, Output};
+use journal::Journal;
+use profile::Profile;
+use replay::{Event, Recording, Stop};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// How many words of memory a machine may use unless told otherwise.
+pub const DEFAULT_MEMORY_LIMIT: usize = 1 << 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+	AwaitingInput,
+	Halted,
+	/// The step budget ran out before the instruction at `pointer`.
+	BudgetExhausted {
+		pointer: usize,
+	},
+	/// The machine came back to a state it was in `period` steps earlier
+	/// without any input or output in between, so it will never stop.
+	InfiniteLoop {
+		pointer: usize,
+		period: u64,
+	},
+}
+
+/// Brent's cycle detection over the machine's whole state: the pointer, the
+/// relative base and memory. The checkpoint is dropped whenever the machine
+/// does any input or output, as those make its future depend on more than its
+/// own state.
+#[derive(Clone, Debug, Default)]
+struct LoopDetector {
+	checkpoint: Option<(usize, i64, Vec<i64>)>,
+	power: u64,
+	length: u64,
+}
+
+impl LoopDetector {
+	fn reset(&mut self) {
+		self.checkpoint = None;
+	}
+
+	/// Returns the period of the loop the machine is in, if it has provably
+	/// entered one.
+	fn check(
+		&mut self,
+		pointer: usize,
+		relative_base: i64,
+		memory: &[i64],
+	) -> Option<u64> {
+		match &self.checkpoint {
+			Some((p, rb, m)) => {
+				self.length += 1;
+				if *p == pointer && *rb == relative_base && m[..] == *memory {
+					return Some(self.length);
+				}
+				if self.length == self.power {
+					self.power *= 2;
+					self.length = 0;
+					self.checkpoint =
+						Some((pointer, relative_base, memory.to_vec()));
+				}
+			}
+			None => {
+				self.power = 1;
+				self.length = 0;
+				self.checkpoint =
+					Some((pointer, relative_base, memory.to_vec()));
+			}
+		}
+		None
+	}
+}
+
+/// A resumable Intcode machine. Running it stops either when the program
+/// halts or when it needs input that has not been provided yet, in which case
+/// it can be given more input and run again.
+///
+/// Memory grows with zeroes whenever the program writes beyond the end of the
+/// loaded image, and reads beyond the end yield zero.
+#[derive(Clone, Debug)]
+pub struct Vm {
+	memory: Vec<i64>,
+	pointer: usize,
+	relative_base: i64,
+	steps: u64,
+	memory_limit: usize,
+	budget: Option<u64>,
+	loop_detector: Option<LoopDetector>,
+	profile: Option<Box<Profile>>,
+	recording: Option<Box<Recording>>,
+	journal: Option<Box<Journal>>,
+	decoded: Option<Vec<Option<Instruction>>>,
+	devices: Bus,
+	input: VecDeque<i64>,
+	output: Vec<i64>,
+}
+
+impl Vm {
+	pub fn new(memory: Vec<i64>) -> Self {
+		Vm {
+			memory,
+			pointer: 0,
+			relative_base: 0,
+			steps: 0,
+			memory_limit: DEFAULT_MEMORY_LIMIT,
+			budget: None,
+			loop_detector: None,
+			profile: None,
+			recording: None,
+			journal: None,
+			decoded: Some(Vec::new()),
+			devices: Bus::default(),
+			input: VecDeque::new(),
+			output: Vec::new(),
+		}
+	}
+
+	pub fn memory(&self) -> &[i64] {
+		&self.memory
+	}
+
+	pub fn memory_mut(&mut self) -> &mut [i64] {
+		if let Some(decoded) = &mut self.decoded {
+			decoded.clear();
+		}
+		&mut self.memory
+	}
+
+	pub fn pointer(&self) -> usize {
+		self.pointer
+	}
+
+	pub fn relative_base(&self) -> i64 {
+		self.relative_base
+	}
+
+	/// The number of instructions executed so far.
+	pub fn steps(&self) -> u64 {
+		self.steps
+	}
+
+	pub fn push_input(&mut self, value: i64) {
+		self.input.push_back(value);
+	}
+
+	pub fn read_output(&mut self) -> Vec<i64> {
+		std::mem::take(&mut self.output)
+	}
+
+	/// Limits how far memory may grow. Accessing an address at or beyond the
+	/// limit faults instead of allocating.
+	pub fn set_memory_limit(&mut self, words: usize) {
+		self.memory_limit = words;
+	}
+
+	/// Limits how many more instructions the machine may execute before it
+	/// stops with `State::BudgetExhausted`. `None` removes the limit.
+	pub fn set_budget(&mut self, steps: Option<u64>) {
+		self.budget = steps;
+	}
+
+	/// The number of instructions left in the budget, if there is one.
+	pub fn budget(&self) -> Option<u64> {
+		self.budget
+	}
+
+	/// Turns detection of provable infinite loops on or off. Detection keeps a
+	/// copy of memory and compares against it as the machine runs.
+	pub fn detect_loops(&mut self, enabled: bool) {
+		self.loop_detector = if enabled {
+			Some(LoopDetector::default())
+		} else {
+			None
+		};
+	}
+
+	/// Turns profiling on or off. Turning it on starts a fresh profile.
+	pub fn set_profiling(&mut self, enabled: bool) {
+		self.profile = if enabled {
+			Some(Box::new(Profile::default()))
+		} else {
+			None
+		};
+	}
+
+	pub fn profile(&self) -> Option<&Profile> {
+		self.profile.as_deref()
+	}
+
+	pub fn take_profile(&mut self) -> Option<Profile> {
+		self.profile.take().map(|profile| *profile)
+	}
+
+	/// Turns recording on or off. Turning it on starts a fresh recording from
+	/// the machine's current state, which logs every value read or written
+	/// from then on and where the machine last stopped.
+	pub fn set_recording(&mut self, enabled: bool) {
+		self.recording = if enabled {
+			Some(Box::new(Recording::start(self)))
+		} else {
+			None
+		};
+	}
+
+	pub fn recording(&self) -> Option<&Recording> {
+		self.recording.as_deref()
+	}
+
+	pub fn take_recording(&mut self) -> Option<Recording> {
+		self.recording.take().map(|recording| *recording)
+	}
+
+	/// Starts keeping a journal of what each instruction changes, of at most
+	/// roughly `bytes` bytes, so that `step_back` can undo them. `None` turns
+	/// it off. Writes to mapped devices cannot be undone.
+	pub fn set_journal(&mut self, bytes: Option<usize>) {
+		self.journal = bytes.map(|bytes| Box::new(Journal::new(bytes)));
+	}
+
+	pub fn journal(&self) -> Option<&Journal> {
+		self.journal.as_deref()
+	}
+
+	/// Undoes the last instruction executed, as far as the journal goes back.
+	/// Any input it read goes back on the front of the machine's own input
+	/// queue; output it produced is not taken back. Returns whether there was
+	/// an instruction to undo.
+	pub fn step_back(&mut self) -> bool {
+		let entry = match self.journal.as_mut().and_then(|j| j.pop()) {
+			Some(entry) => entry,
+			None => return false,
+		};
+		for &(address, value) in entry.writes.iter().rev() {
+			self.memory[address] = value;
+			self.invalidate(address);
+		}
+		self.memory.truncate(entry.length);
+		if let Some(decoded) = &mut self.decoded {
+			decoded.truncate(entry.length);
+		}
+		if let Some(value) = entry.input {
+			self.input.push_front(value);
+		}
+		self.pointer = entry.pointer;
+		self.relative_base = entry.relative_base;
+		self.steps = entry.step;
+		if let Some(budget) = &mut self.budget {
+			*budget += 1;
+		}
+		if let Some(detector) = &mut self.loop_detector {
+			detector.reset();
+		}
+		true
+	}
+
+	/// Turns the cache of decoded instructions on or off. It is on by default;
+	/// turning it off makes the machine decode every instruction it executes.
+	pub fn set_decode_cache(&mut self, enabled: bool) {
+		self.decoded = if enabled { Some(Vec::new()) } else { None };
+	}
+
+	/// Maps `device` into the addresses from `start` on, and returns a handle
+	/// for looking at it from outside the machine. A cloned machine shares
+	/// its devices with the original, and snapshots do not include them.
+	///
+	/// Panics if the range overlaps a device already mapped.
+	pub fn map_device<D: Device + 'static>(
+		&mut self,
+		start: usize,
+		device: D,
+	) -> Arc<Mutex<D>> {
+		self.devices.map(start, device)
+	}
+
+	/// The word at `address`, from a device if one is mapped there, without
+	/// any of the side effects a read by the program might have.
+	pub fn peek(&self, address: usize) -> i64 {
+		self.devices
+			.peek(address)
+			.unwrap_or_else(|| self.memory.get(address).copied().unwrap_or(0))
+	}
+
+	/// Runs against the machine's own input queue and output buffer.
+	pub fn run(&mut self) -> Result<State, Fault> {
+		let mut input = std::mem::take(&mut self.input);
+		let mut output = std::mem::take(&mut self.output);
+		let result = self.run_with(&mut input, &mut output);
+		self.input = input;
+		self.output = output;
+		result
+	}
+
+	pub fn step(&mut self) -> Result<Option<State>, Fault> {
+		let mut input = std::mem::take(&mut self.input);
+		let mut output = std::mem::take(&mut self.output);
+		let result = self.step_with(&mut input, &mut output);
+		self.input = input;
+		self.output = output;
+		result
+	}
+
+	/// Runs until the program halts or `input` has nothing to give.
+	pub fn run_with(
+		&mut self,
+		mut input: impl Input,
+		mut output: impl Output,
+	) -> Result<State, Fault> {
+		loop {
+			if let Some(state) = self.step_with(&mut input, &mut output)? {
+				return Ok(state);
+			}
+		}
+	}
+
+	/// Runs as a future that waits for input instead of stopping, and so only
+	/// resolves to `State::AwaitingInput` once `input` has ended. It yields
+	/// now and then so that other machines on the executor get to run. The
+	/// machine's own input queue is ignored: every value comes from `input`.
+	pub async fn run_async(
+		&mut self,
+		mut input: impl AsyncInput,
+		mut output: impl Output,
+	) -> Result<State, Fault> {
+		const STEPS_PER_YIELD: u32 = 10_000;
+		let mut pending = VecDeque::new();
+		let mut steps = 0;
+		loop {
+			match self.step_with(&mut pending, &mut output)? {
+				Some(State::AwaitingInput) => {
+					match executor::read(&mut input).await {
+						Some(value) => pending.push_back(value),
+						None => return Ok(State::AwaitingInput),
+					}
+				}
+				Some(state) => return Ok(state),
+				None => {}
+			}
+			steps += 1;
+			if steps % STEPS_PER_YIELD == 0 {
+				executor::yield_now().await;
+			}
+		}
+	}
+
+	/// Executes a single instruction. Returns the state the machine is stuck
+	/// in if it cannot make progress, without moving the pointer.
+	pub fn step_with(
+		&mut self,
+		input: impl Input,
+		output: impl Output,
+	) -> Result<Option<State>, Fault> {
+		let result = self.execute(input, output);
+		if let Some(recording) = &mut self.recording {
+			if let Some(stop) = Stop::new(&result) {
+				recording.stop = Some((self.steps, stop));
+			}
+		}
+		result
+	}
+
+	fn execute(
+		&mut self,
+		mut input: impl Input,
+		mut output: impl Output,
+	) -> Result<Option<State>, Fault> {
+		if self.budget == Some(0) {
+			return Ok(Some(State::BudgetExhausted {
+				pointer: self.pointer,
+			}));
+		}
+		let instruction = self.fetch()?;
+		if let Some(journal) = &mut self.journal {
+			journal.begin(
+				self.steps,
+				self.pointer,
+				self.relative_base,
+				self.memory.len(),
+			);
+		}
+		let mut io = false;
+		let accesses = self.devices.accesses();
+		let mut next = self.pointer + instruction.width();
+		match instruction {
+			Instruction::Add(a, b, c) => {
+				let value = self.load(a)?.checked_add(self.load(b)?);
+				self.write(c, value.ok_or_else(|| self.overflow())?)?;
+			}
+			Instruction::Multiply(a, b, c) => {
+				let value = self.load(a)?.checked_mul(self.load(b)?);
+				self.write(c, value.ok_or_else(|| self.overflow())?)?;
+			}
+			Instruction::Input(a) => {
+				self.checked_address(a, Access::Write)?;
+				let waiting = self.profile.as_ref().map(|_| Instant::now());
+				let value = input.read();
+				if let (Some(profile), Some(waiting)) =
+					(&mut self.profile, waiting)
+				{
+					profile.input_wait += waiting.elapsed();
+				}
+				match value {
+					Some(s) => {
+						self.record(Event::Input(s));
+						if let Some(journal) = &mut self.journal {
+							journal.read(s);
+						}
+						self.write(a, s)?;
+						io = true;
+					}
+					None => return Ok(Some(State::AwaitingInput)),
+				}
+			}
+			Instruction::Output(a) => {
+				let value = self.load(a)?;
+				output.write(value);
+				self.record(Event::Output(value));
+				io = true;
+			}
+			Instruction::JumpIfTrue(a, b) => {
+				if self.load(a)? != 0 {
+					next = self.jump_target(b)?;
+				}
+			}
+			Instruction::JumpIfFalse(a, b) => {
+				if self.load(a)? == 0 {
+					next = self.jump_target(b)?;
+				}
+			}
+			Instruction::LessThan(a, b, c) => {
+				let value = if self.load(a)? < self.load(b)? { 1 } else { 0 };
+				self.write(c, value)?;
+			}
+			Instruction::Equals(a, b, c) => {
+				let value = if self.load(a)? == self.load(b)? { 1 } else { 0 };
+				self.write(c, value)?;
+			}
+			Instruction::AdjustRelativeBase(a) => {
+				let value = self.relative_base.checked_add(self.load(a)?);
+				self.relative_base = value.ok_or_else(|| self.overflow())?;
+			}
+			Instruction::Halt => {
+				self.retire(&instruction);
+				return Ok(Some(State::Halted));
+			}
+		}
+		self.retire(&instruction);
+		self.pointer = next;
+		if let Some(detector) = &mut self.loop_detector {
+			// Devices can change under the program much like input can.
+			if io || self.devices.accesses() != accesses {
+				detector.reset();
+			} else if let Some(period) =
+				detector.check(self.pointer, self.relative_base, &self.memory)
+			{
+				return Ok(Some(State::InfiniteLoop {
+					pointer: self.pointer,
+					period,
+				}));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Logs a value read or written by the instruction at the pointer.
+	fn record(&mut self, event: Event) {
+		if let Some(recording) = &mut self.recording {
+			recording.events.push((self.steps + 1, event));
+		}
+	}
+
+	/// Decodes the instruction at the pointer, or reuses the decoding from the
+	/// last time it ran if nothing has written over it since.
+	fn fetch(&mut self) -> Result<Instruction, Fault> {
+		let pointer = self.pointer;
+		let decoded = match &mut self.decoded {
+			Some(decoded) => decoded,
+			None => return Instruction::decode(pointer, &self.memory),
+		};
+		if let Some(Some(instruction)) = decoded.get(pointer) {
+			return Ok(*instruction);
+		}
+		let instruction = Instruction::decode(pointer, &self.memory)?;
+		if pointer < self.memory.len() {
+			if pointer >= decoded.len() {
+				decoded.resize(self.memory.len(), None);
+			}
+			decoded[pointer] = Some(instruction);
+		}
+		Ok(instruction)
+	}
+
+	/// Accounts for the instruction at the pointer having been executed.
+	fn retire(&mut self, instruction: &Instruction) {
+		self.steps += 1;
+		if let Some(budget) = &mut self.budget {
+			*budget -= 1;
+		}
+		if let Some(journal) = &mut self.journal {
+			journal.commit();
+		}
+		if let Some(profile) = &mut self.profile {
+			*profile.executions.entry(self.pointer).or_insert(0) += 1;
+			*profile.opcodes.entry(instruction.mnemonic()).or_insert(0) += 1;
+		}
+	}
+
+	fn word(&self) -> i64 {
+		self.memory.get(self.pointer).copied().unwrap_or(0)
+	}
+
+	fn overflow(&self) -> Fault {
+		Fault::Overflow {
+			pointer: self.pointer,
+			word: self.word(),
+		}
+	}
+
+	fn jump_target(&mut self, parameter: Parameter) -> Result<usize, Fault> {
+		let target = self.load(parameter)?;
+		usize::try_from(target).map_err(|_| Fault::NegativeAddress {
+			pointer: self.pointer,
+			word: self.word(),
+			address: target,
+		})
+	}
+
+	/// The address a position or relative parameter refers to. Immediate
+	/// parameters have no address, so asking for one is a fault.
+	pub fn address(&self, parameter: Parameter) -> Result<usize, Fault> {
+		let address = match parameter.mode {
+			Mode::Position => parameter.value,
+			Mode::Relative => self
+				.relative_base
+				.checked_add(parameter.value)
+				.ok_or_else(|| self.overflow())?,
+			Mode::Immediate => {
+				return Err(Fault::ImmediateWrite {
+					pointer: self.pointer,
+					word: self.word(),
+				})
+			}
+		};
+		usize::try_from(address).map_err(|_| Fault::NegativeAddress {
+			pointer: self.pointer,
+			word: self.word(),
+			address,
+		})
+	}
+
+	fn checked_address(
+		&self,
+		parameter: Parameter,
+		access: Access,
+	) -> Result<usize, Fault> {
+		let address = self.address(parameter)?;
+		if address >= self.memory_limit {
+			return Err(Fault::OutOfBounds {
+				pointer: self.pointer,
+				word: self.word(),
+				address,
+				access,
+			});
+		}
+		Ok(address)
+	}
+
+	/// The value a parameter resolves to.
+	pub fn read(&self, parameter: Parameter) -> Result<i64, Fault> {
+		match parameter.mode {
+			Mode::Immediate => Ok(parameter.value),
+			_ => {
+				let address = self.checked_address(parameter, Access::Read)?;
+				Ok(self.peek(address))
+			}
+		}
+	}
+
+	/// Reads a parameter on behalf of the running program.
+	fn load(&mut self, parameter: Parameter) -> Result<i64, Fault> {
+		if parameter.mode == Mode::Immediate {
+			return Ok(parameter.value);
+		}
+		let address = self.checked_address(parameter, Access::Read)?;
+		if let Some(profile) = &mut self.profile {
+			*profile.reads.entry(address).or_insert(0) += 1;
+		}
+		match self.devices.read(address) {
+			Some(value) => Ok(value),
+			None => Ok(self.memory.get(address).copied().unwrap_or(0)),
+		}
+	}
+
+	fn write(&mut self, parameter: Parameter, value: i64) -> Result<(), Fault> {
+		let address = self.checked_address(parameter, Access::Write)?;
+		if let Some(profile) = &mut self.profile {
+			*profile.writes.entry(address).or_insert(0) += 1;
+		}
+		if self.devices.write(address, value) {
+			return Ok(());
+		}
+		if let Some(journal) = &mut self.journal {
+			journal
+				.wrote(address, self.memory.get(address).copied().unwrap_or(0));
+		}
+		if address >= self.memory.len() {
+			self.memory.resize(address + 1, 0);
+		}
+		self.memory[address] = value;
+		self.invalidate(address);
+		Ok(())
+	}
+
+	/// Drops any cached decoding that `address` is part of.
+	fn invalidate(&mut self, address: usize) {
+		if let Some(decoded) = &mut self.decoded {
+			// Any instruction overlapping the address starts at most three
+			// words before it.
+			let start = address.saturating_sub(3).min(decoded.len());
+			let end = (address + 1).min(decoded.len());
+			for entry in &mut decoded[start..end] {
+				*entry = None;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The result of each step and the machine it left behind: the pointer,
+	/// the output and the memory.
+	type Step = (Result<Option<State>, Fault>, usize, Vec<i64>, Vec<i64>);
+
+	fn trajectory(program: &[i64], cached: bool) -> Vec<Step> {
+		let mut vm = Vm::new(program.to_vec());
+		vm.set_decode_cache(cached);
+		// A stale decoding would loop forever, so stop it instead.
+		vm.set_budget(Some(100));
+		let mut steps = Vec::new();
+		loop {
+			let result = vm.step();
+			let done = result != Ok(None);
+			let output = vm.read_output();
+			steps.push((result, vm.pointer(), output, vm.memory().to_vec()));
+			if done {
+				return steps;
+			}
+		}
+	}
+
+	#[test]
+	fn decode_cache_sees_code_the_program_rewrites() {
+		let program = [
+			104, 7, // OUT #7, whose operand the loop bumps
+			1001, 1, 1, 1, // ADD [1], #1, [1]
+			1008, 1, 10, 20, // EQ [1], #10, [20]
+			1006, 20, 0, // JF [20], #0
+			1101, 99, 0, 0, // ADD #99, #0, [0], turning OUT into HLT
+			1105, 1, 0, // JT #1, #0
+			0,
+		];
+		let uncached = trajectory(&program, false);
+		assert_eq!(trajectory(&program, true), uncached);
+		let outputs: Vec<i64> =
+			uncached.iter().flat_map(|step| step.2.clone()).collect();
+		assert_eq!(outputs, [7, 8, 9]);
+		let last = uncached.last().unwrap();
+		assert_eq!((last.0, last.1), (Ok(Some(State::Halted)), 0));
+	}
+}
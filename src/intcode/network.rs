@@ -0,0 +1,351 @@
+use super::io::{Input, Output};
+use super::{Fault, State, Vm};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+struct Node {
+	vm: Vm,
+	targets: Vec<usize>,
+	input: VecDeque<i64>,
+}
+
+/// Several machines, each running on its own thread, whose outputs feed the
+/// inputs of others.
+#[derive(Default)]
+pub struct Network {
+	nodes: Vec<Node>,
+}
+
+pub struct Outcome {
+	pub vm: Vm,
+	pub result: Result<State, Fault>,
+	/// Every value the machine produced, whether or not it was delivered.
+	pub outputs: Vec<i64>,
+}
+
+pub struct Report {
+	pub machines: Vec<Outcome>,
+	/// Whether the run ended with every remaining machine waiting for input
+	/// that could never arrive.
+	pub deadlocked: bool,
+}
+
+struct Shared {
+	queues: Vec<VecDeque<i64>>,
+	running: Vec<bool>,
+	waiting: Vec<bool>,
+	deadlocked: bool,
+}
+
+/// Whether some machine is still running and every one that is waits for
+/// input with nothing queued for it, so none can make progress.
+fn is_deadlocked(
+	running: &[bool],
+	waiting: &[bool],
+	queues: &[VecDeque<i64>],
+) -> bool {
+	let mut any = false;
+	for (i, &running) in running.iter().enumerate() {
+		if running {
+			if !waiting[i] || !queues[i].is_empty() {
+				return false;
+			}
+			any = true;
+		}
+	}
+	any
+}
+
+impl Shared {
+	fn is_deadlocked(&self) -> bool {
+		is_deadlocked(&self.running, &self.waiting, &self.queues)
+	}
+
+	fn check_deadlock(&mut self, wake: &Condvar) {
+		if self.is_deadlocked() {
+			self.deadlocked = true;
+			wake.notify_all();
+		}
+	}
+}
+
+struct Bus {
+	shared: Mutex<Shared>,
+	wake: Condvar,
+}
+
+struct Inbox {
+	id: usize,
+	bus: Arc<Bus>,
+}
+
+struct Outbox {
+	targets: Vec<usize>,
+	outputs: Vec<i64>,
+	bus: Arc<Bus>,
+}
+
+impl Input for Inbox {
+	fn read(&mut self) -> Option<i64> {
+		let mut shared = self.bus.shared.lock().unwrap();
+		loop {
+			if let Some(value) = shared.queues[self.id].pop_front() {
+				return Some(value);
+			}
+			if shared.deadlocked {
+				return None;
+			}
+			shared.waiting[self.id] = true;
+			shared.check_deadlock(&self.bus.wake);
+			if shared.deadlocked {
+				return None;
+			}
+			shared = self.bus.wake.wait(shared).unwrap();
+			shared.waiting[self.id] = false;
+		}
+	}
+}
+
+impl Output for Outbox {
+	fn write(&mut self, value: i64) {
+		self.outputs.push(value);
+		if self.targets.is_empty() {
+			return;
+		}
+		let mut shared = self.bus.shared.lock().unwrap();
+		for &target in &self.targets {
+			shared.queues[target].push_back(value);
+		}
+		self.bus.wake.notify_all();
+	}
+}
+
+impl Network {
+	pub fn new() -> Self {
+		Network::default()
+	}
+
+	/// Adds a machine and returns its index. The machine reads only from the
+	/// network, so anything left in its own input queue is ignored.
+	pub fn add(&mut self, vm: Vm) -> usize {
+		self.nodes.push(Node {
+			vm,
+			targets: Vec::new(),
+			input: VecDeque::new(),
+		});
+		self.nodes.len() - 1
+	}
+
+	/// Routes every output of `from` to the input of `to`. A machine may feed
+	/// several others, and several may feed one.
+	pub fn connect(&mut self, from: usize, to: usize) {
+		self.nodes[from].targets.push(to);
+	}
+
+	/// Queues a value for `to` to read before anything sent to it at runtime.
+	pub fn send(&mut self, to: usize, value: i64) {
+		self.nodes[to].input.push_back(value);
+	}
+
+	/// Runs every machine to completion and waits for them all to stop.
+	pub fn run(self) -> Report {
+		let n = self.nodes.len();
+		let mut queues = Vec::with_capacity(n);
+		let mut nodes = Vec::with_capacity(n);
+		for node in self.nodes {
+			queues.push(node.input);
+			nodes.push((node.vm, node.targets));
+		}
+		let bus = Arc::new(Bus {
+			shared: Mutex::new(Shared {
+				queues,
+				running: vec![true; n],
+				waiting: vec![false; n],
+				deadlocked: false,
+			}),
+			wake: Condvar::new(),
+		});
+
+		let handles: Vec<_> = nodes
+			.into_iter()
+			.enumerate()
+			.map(|(id, (mut vm, targets))| {
+				let bus = Arc::clone(&bus);
+				thread::spawn(move || {
+					let inbox = Inbox {
+						id,
+						bus: Arc::clone(&bus),
+					};
+					let mut outbox = Outbox {
+						targets,
+						outputs: Vec::new(),
+						bus: Arc::clone(&bus),
+					};
+					let result = vm.run_with(inbox, &mut outbox);
+					let mut shared = bus.shared.lock().unwrap();
+					shared.running[id] = false;
+					shared.waiting[id] = false;
+					shared.check_deadlock(&bus.wake);
+					Outcome {
+						vm,
+						result,
+						outputs: outbox.outputs,
+					}
+				})
+			})
+			.collect();
+
+		let machines = handles
+			.into_iter()
+			.map(|handle| handle.join().expect("machine thread panicked"))
+			.collect();
+		let deadlocked = bus.shared.lock().unwrap().deadlocked;
+		Report {
+			machines,
+			deadlocked,
+		}
+	}
+
+	/// Runs the machines in turn on the current thread, each until it halts
+	/// or runs out of input, until none can make progress. Unlike `run`, the
+	/// order in which values from several machines reach a shared input is
+	/// deterministic.
+	pub fn run_in_turn(self) -> Report {
+		let mut nodes = self.nodes;
+		let mut outputs = vec![Vec::new(); nodes.len()];
+		let mut results: Vec<Option<Result<State, Fault>>> =
+			vec![None; nodes.len()];
+		loop {
+			let mut progress = false;
+			for i in 0..nodes.len() {
+				if let Some(Ok(State::Halted)) | Some(Err(_)) = results[i] {
+					continue;
+				}
+				let node = &mut nodes[i];
+				let steps = node.vm.steps();
+				let mut produced = Vec::new();
+				let result = node.vm.run_with(&mut node.input, &mut produced);
+				progress |= node.vm.steps() != steps;
+				for &target in &node.targets.clone() {
+					nodes[target].input.extend(&produced);
+				}
+				outputs[i].extend(produced);
+				results[i] = Some(result);
+			}
+			if !progress {
+				break;
+			}
+		}
+		// A machine that stopped for any other reason will not run again.
+		let waiting: Vec<bool> = results
+			.iter()
+			.map(|r| matches!(r, Some(Ok(State::AwaitingInput))))
+			.collect();
+		let queues: Vec<VecDeque<i64>> =
+			nodes.iter().map(|node| node.input.clone()).collect();
+		let deadlocked = is_deadlocked(&waiting, &waiting, &queues);
+		let machines = nodes
+			.into_iter()
+			.zip(outputs)
+			.zip(results)
+			.map(|((node, outputs), result)| Outcome {
+				vm: node.vm,
+				result: result.unwrap(),
+				outputs,
+			})
+			.collect();
+		Report {
+			machines,
+			deadlocked,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The feedback loop example from day 7.
+	const FEEDBACK: [i64; 29] = [
+		3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27,
+		1001, 28, -1, 28, 1005, 28, 6, 99, 0, 0, 5,
+	];
+
+	#[test]
+	fn ring_carries_the_signal_round() {
+		let mut network = Network::new();
+		for (i, &phase) in [9, 8, 7, 6, 5].iter().enumerate() {
+			network.add(Vm::new(FEEDBACK.to_vec()));
+			network.send(i, phase);
+			network.connect(i, (i + 1) % 5);
+		}
+		network.send(0, 0);
+		let report = network.run();
+		assert!(!report.deadlocked);
+		for machine in &report.machines {
+			assert_eq!(machine.result, Ok(State::Halted));
+		}
+		assert_eq!(report.machines[4].outputs.last(), Some(&139629729));
+	}
+
+	#[test]
+	fn run_in_turn_reports_deadlock_as_run_does() {
+		let networks: [(fn() -> Network, bool); 3] = [
+			// Two machines waiting on each other.
+			(
+				|| {
+					let mut network = Network::new();
+					network.add(Vm::new(vec![3, 0, 4, 0, 99]));
+					network.add(Vm::new(vec![3, 0, 4, 0, 99]));
+					network.connect(0, 1);
+					network.connect(1, 0);
+					network
+				},
+				true,
+			),
+			// One machine halts without ever feeding the other.
+			(
+				|| {
+					let mut network = Network::new();
+					network.add(Vm::new(vec![99]));
+					network.add(Vm::new(vec![3, 0, 4, 0, 99]));
+					network.connect(0, 1);
+					network
+				},
+				true,
+			),
+			// One machine halts after feeding the other all it needs.
+			(
+				|| {
+					let mut network = Network::new();
+					network.add(Vm::new(vec![104, 7, 99]));
+					network.add(Vm::new(vec![3, 0, 4, 0, 99]));
+					network.connect(0, 1);
+					network
+				},
+				false,
+			),
+		];
+		for (build, deadlocked) in networks.iter() {
+			assert_eq!(build().run().deadlocked, *deadlocked);
+			assert_eq!(build().run_in_turn().deadlocked, *deadlocked);
+		}
+	}
+
+	#[test]
+	fn pair_waiting_on_each_other_deadlocks() {
+		// Each machine echoes its input, so neither ever gets one.
+		let mut network = Network::new();
+		let a = network.add(Vm::new(vec![3, 0, 4, 0, 99]));
+		let b = network.add(Vm::new(vec![3, 0, 4, 0, 99]));
+		network.connect(a, b);
+		network.connect(b, a);
+		let report = network.run();
+		assert!(report.deadlocked);
+		for machine in &report.machines {
+			assert_eq!(machine.result, Ok(State::AwaitingInput));
+			assert_eq!(machine.outputs, []);
+		}
+	}
+}
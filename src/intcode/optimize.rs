@@ -0,0 +1,444 @@
+//! A peephole optimizer that rewrites an Intcode image into an equivalent one
+//! that takes fewer steps to run.
+//!
+//! Every instruction keeps its address and width, so nothing is relocated and
+//! any address the program computes still means the same. Within that limit
+//! the optimizer
+//!
+//! - folds arithmetic and comparisons whose inputs are both immediate into
+//!   `ADD #value, #0, dst`, and a jump whose condition is immediate and holds
+//!   into `JT #1, target`;
+//! - points each jump with an immediate target past the unconditional jumps,
+//!   jumps that are never taken and jumps to the next instruction that it
+//!   would otherwise land on. A jump whose condition could fault to read, such
+//!   as one at a negative address or past the default memory limit, is never
+//!   skipped.
+//!
+//! A jump to the next instruction that is reached by falling through stays,
+//! as removing it would move everything after it.
+//!
+//! Only instructions the control-flow graph reaches from address 0 are
+//! rewritten, and only if no other reachable instruction overlaps them and no
+//! instruction writes any of their words or reads one as data. An instruction
+//! whose immediate operand the program writes over is left as it is. The
+//! image is refused whenever the graph might not be the whole story: a jump
+//! to a computed target, any parameter in relative mode, or an instruction
+//! whose opcode, address operand, jump condition or jump target the program
+//! writes over, or a word it writes that would be run once decodable.
+
+use super::cfg::{Exit, Graph};
+use super::{Instruction, Mode, Parameter, DEFAULT_MEMORY_LIMIT};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// One instruction the optimizer changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rewrite {
+	pub address: usize,
+	pub before: Instruction,
+	pub after: Instruction,
+}
+
+impl fmt::Display for Rewrite {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let before = self.before.to_string();
+		write!(f, "{:>6}  {:<28} -> {}", self.address, before, self.after)
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Optimized {
+	pub memory: Vec<i64>,
+	/// The instructions changed, by address.
+	pub rewrites: Vec<Rewrite>,
+}
+
+/// Why an image was left alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Refusal {
+	/// The instruction whose writes cannot be pinned down.
+	pub address: usize,
+	pub reason: &'static str,
+}
+
+impl fmt::Display for Refusal {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "the instruction at {} {}", self.address, self.reason)
+	}
+}
+
+fn immediate(p: Parameter) -> Option<i64> {
+	if p.mode == Mode::Immediate {
+		Some(p.value)
+	} else {
+		None
+	}
+}
+
+fn constant(value: i64) -> Parameter {
+	Parameter {
+		mode: Mode::Immediate,
+		value,
+	}
+}
+
+/// The words the reachable instructions write and read as data.
+fn accesses(
+	instructions: &BTreeMap<usize, Instruction>,
+) -> Result<(BTreeSet<usize>, BTreeSet<usize>), Refusal> {
+	let mut written = BTreeSet::new();
+	let mut read = BTreeSet::new();
+	for (&address, instruction) in instructions {
+		let parameters = instruction.parameters();
+		let destination =
+			instruction.destination().map(|_| parameters.len() - 1);
+		for (i, p) in parameters.into_iter().enumerate() {
+			match p.mode {
+				Mode::Position if p.value < 0 => {}
+				Mode::Position if Some(i) == destination => {
+					written.insert(p.value as usize);
+				}
+				Mode::Position => {
+					read.insert(p.value as usize);
+				}
+				Mode::Relative => {
+					return Err(Refusal {
+						address,
+						reason: "uses the relative base",
+					})
+				}
+				Mode::Immediate => {}
+			}
+		}
+	}
+	// Writing over an immediate operand changes a value but not where the
+	// instruction reads, writes or goes; any other word could change those.
+	for (&address, instruction) in instructions {
+		let jump = matches!(
+			instruction,
+			Instruction::JumpIfTrue(..) | Instruction::JumpIfFalse(..)
+		);
+		let parameters = instruction.parameters();
+		let harmless = |a: usize| {
+			a > address
+				&& !jump && parameters[a - address - 1].mode == Mode::Immediate
+		};
+		if (address..address + instruction.width())
+			.any(|a| written.contains(&a) && !harmless(a))
+		{
+			return Err(Refusal {
+				address,
+				reason: "is written over in a way that changes what it does",
+			});
+		}
+	}
+	Ok((written, read))
+}
+
+/// Whether reading `p` cannot fault on a machine with the default memory
+/// limit, so an instruction that only reads it can be skipped.
+fn readable(p: Parameter) -> bool {
+	match p.mode {
+		Mode::Immediate => true,
+		Mode::Position => (0..DEFAULT_MEMORY_LIMIT as i64).contains(&p.value),
+		Mode::Relative => false,
+	}
+}
+
+/// Where control goes from a jump at `address` that always goes the same
+/// way, whether taken or not.
+fn skip(address: usize, instruction: &Instruction) -> Option<usize> {
+	let (condition, target, jump_if) = match *instruction {
+		Instruction::JumpIfTrue(a, b) => (a, b, true),
+		Instruction::JumpIfFalse(a, b) => (a, b, false),
+		_ => return None,
+	};
+	let next = address + instruction.width();
+	let target = immediate(target).filter(|&t| t >= 0).map(|t| t as usize);
+	match immediate(condition) {
+		Some(c) if (c != 0) == jump_if => target,
+		Some(_) => Some(next),
+		None if target == Some(next) && readable(condition) => Some(next),
+		None => None,
+	}
+}
+
+/// The equivalent of `instruction` with its inputs worked out, if they are
+/// all immediate.
+fn fold(instruction: &Instruction) -> Option<Instruction> {
+	let (value, c) = match *instruction {
+		Instruction::Add(a, b, c) => {
+			(immediate(a)?.checked_add(immediate(b)?)?, c)
+		}
+		Instruction::Multiply(a, b, c) => {
+			(immediate(a)?.checked_mul(immediate(b)?)?, c)
+		}
+		Instruction::LessThan(a, b, c) => {
+			((immediate(a)? < immediate(b)?) as i64, c)
+		}
+		Instruction::Equals(a, b, c) => {
+			((immediate(a)? == immediate(b)?) as i64, c)
+		}
+		Instruction::JumpIfTrue(a, b) if immediate(a)? != 0 => {
+			return Some(Instruction::JumpIfTrue(constant(1), b))
+		}
+		Instruction::JumpIfFalse(a, b) if immediate(a)? == 0 => {
+			return Some(Instruction::JumpIfTrue(constant(1), b))
+		}
+		_ => return None,
+	};
+	Some(Instruction::Add(constant(value), constant(0), c))
+}
+
+/// The same jump with its target replaced.
+fn retarget(instruction: &Instruction, target: usize) -> Instruction {
+	let target = constant(target as i64);
+	match *instruction {
+		Instruction::JumpIfTrue(a, _) => Instruction::JumpIfTrue(a, target),
+		Instruction::JumpIfFalse(a, _) => Instruction::JumpIfFalse(a, target),
+		other => other,
+	}
+}
+
+/// The instructions the graph reaches, and those among them that overlap
+/// another or a word the graph found could not be run from, as rewriting one
+/// would change the other.
+fn reached(
+	memory: &[i64],
+	graph: &Graph,
+) -> (BTreeMap<usize, Instruction>, BTreeSet<usize>) {
+	let instructions: BTreeMap<usize, Instruction> = graph
+		.blocks
+		.values()
+		.flat_map(|block| block.instructions.iter().copied())
+		.collect();
+	let mut spans: Vec<(usize, usize)> = instructions
+		.iter()
+		.map(|(&address, instruction)| (address, address + instruction.width()))
+		.collect();
+	spans.extend(graph.blocks.values().filter_map(|block| match block.exit {
+		Exit::Invalid(address) => {
+			let width =
+				Instruction::decode(address, memory).map_or(1, |i| i.width());
+			Some((address, address + width))
+		}
+		_ => None,
+	}));
+	spans.sort_unstable();
+	let mut overlapping = BTreeSet::new();
+	let mut previous: Option<(usize, usize)> = None;
+	for (address, end) in spans {
+		if let Some((start, furthest)) = previous {
+			if address < furthest {
+				overlapping.insert(start);
+				overlapping.insert(address);
+			}
+		}
+		if previous.is_none_or(|(_, furthest)| end > furthest) {
+			previous = Some((address, end));
+		}
+	}
+	(instructions, overlapping)
+}
+
+pub fn optimize(memory: &[i64]) -> Result<Optimized, Refusal> {
+	let graph = Graph::build(memory);
+	if let Some(&address) = graph.unresolved().first() {
+		return Err(Refusal {
+			address,
+			reason: "jumps to an address computed at run time",
+		});
+	}
+	let (original, overlapping) = reached(memory, &graph);
+	let (written, read) = accesses(&original)?;
+	// A word that cannot be decoded yet might be once the program writes it.
+	for block in graph.blocks.values() {
+		if let Exit::Invalid(address) = block.exit {
+			if (address..address + 4).any(|a| written.contains(&a)) {
+				return Err(Refusal {
+					address,
+					reason: "is written before it can be run",
+				});
+			}
+		}
+	}
+	let fixed: BTreeSet<usize> = original
+		.iter()
+		.filter(|(&address, instruction)| {
+			!overlapping.contains(&address)
+				&& (address..address + instruction.width())
+					.all(|a| !written.contains(&a) && !read.contains(&a))
+		})
+		.map(|(&address, _)| address)
+		.collect();
+
+	let mut instructions = original.clone();
+	for &address in &fixed {
+		if let Some(after) = fold(&instructions[&address]) {
+			instructions.insert(address, after);
+		}
+	}
+
+	for &address in &fixed {
+		let before = instructions[&address];
+		let target = match before {
+			Instruction::JumpIfTrue(_, b) | Instruction::JumpIfFalse(_, b) => {
+				match immediate(b) {
+					Some(t) if t >= 0 => t as usize,
+					_ => continue,
+				}
+			}
+			_ => continue,
+		};
+		let mut destination = target;
+		let mut seen = BTreeSet::new();
+		while seen.insert(destination) && fixed.contains(&destination) {
+			match skip(destination, &instructions[&destination]) {
+				Some(next) => destination = next,
+				None => break,
+			}
+		}
+		instructions.insert(address, retarget(&before, destination));
+	}
+
+	let mut memory = memory.to_vec();
+	let mut rewrites = Vec::new();
+	for &address in &fixed {
+		let (before, after) = (original[&address], instructions[&address]);
+		if before != after {
+			let words = after.encode();
+			memory[address..address + words.len()].copy_from_slice(&words);
+			rewrites.push(Rewrite {
+				address,
+				before,
+				after,
+			});
+		}
+	}
+	Ok(Optimized { memory, rewrites })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::intcode::asm;
+	use crate::intcode::fuzz::{generate, Case, Ending, Engine, Features, Rng};
+
+	/// Runs `program` and its optimized image on the reference interpreter
+	/// and checks that they agree, returning the optimizer's result.
+	fn check(program: &[i64], input: &[i64]) -> Result<Optimized, Refusal> {
+		let optimized = optimize(program)?;
+		let run = |memory: &[i64]| {
+			Engine::Reference.run(&Case {
+				program: memory.to_vec(),
+				input: input.to_vec(),
+			})
+		};
+		let (before, after) = (run(program), run(&optimized.memory));
+		let context =
+			format!("{:?}\nbefore: {}\nafter:  {}", program, before, after);
+		if before.ending == Ending::OutOfSteps {
+			assert!(after.outputs.starts_with(&before.outputs), "{}", context);
+			return Ok(optimized);
+		}
+		assert_eq!(before.ending, after.ending, "{}", context);
+		assert_eq!(before.outputs, after.outputs, "{}", context);
+		assert!(after.steps <= before.steps, "{}", context);
+		assert_eq!(before.memory.len(), after.memory.len(), "{}", context);
+		for (address, (a, b)) in
+			before.memory.iter().zip(&after.memory).enumerate()
+		{
+			let rewritten = optimized.rewrites.iter().any(|r| {
+				(r.address..r.address + r.after.width()).contains(&address)
+			});
+			assert!(a == b || rewritten, "{}", context);
+		}
+		Ok(optimized)
+	}
+
+	#[test]
+	fn refuses_data_read_through_the_relative_base() {
+		let program = [109, 10, 204, 1, 99, 0, 0, 0, 0, 0, 1101, 2, 3, 20];
+		assert!(check(&program, &[]).is_err());
+	}
+
+	#[test]
+	fn refuses_a_patched_read_operand() {
+		let program = [1101, 8, 0, 5, 4, 0, 99, 1101, 2, 3, 30];
+		assert!(check(&program, &[]).is_err());
+	}
+
+	#[test]
+	fn keeps_a_jump_whose_condition_faults() {
+		// The jumps at 3 go to the next instruction, but reading their
+		// condition faults, so threading the first jump past them would make
+		// the program halt instead.
+		let programs: [&[i64]; 3] = [
+			&[1105, 1, 3, 1005, -1, 6, 99],
+			&[1105, 1, 3, 1005, 100000000, 6, 99],
+			&[1005, 100000000, 6],
+		];
+		for program in programs {
+			assert_eq!(check(program, &[]).unwrap().rewrites, []);
+		}
+	}
+
+	#[test]
+	fn folds_constants_and_threads_jumps() {
+		let source = "
+			        IN [n]
+			        ADD #2, #3, [k]
+			loop:   JF [n], #a
+			        MUL #4, #5, [m]
+			        ADD [s], [k], [s]
+			        ADD [s], [m], [s]
+			        ADD [n], #-1, [n]
+			        JT #1, #hop
+			a:      JT #1, #b
+			b:      JF #7, #99
+			        ADD #0, #0, [patch+1]
+			patch:  OUT #1
+			        OUT [s]
+			        HLT
+			hop:    JF #0, #next
+			next:   JT [n], #loop
+			        JT #1, #loop
+			n: DB 0
+			k: DB 0
+			m: DB 0
+			s: DB 0
+		";
+		let program = asm::assemble(source).unwrap();
+		let optimized = check(&program, &[3]).unwrap();
+		let rewritten: Vec<String> = optimized
+			.rewrites
+			.iter()
+			.map(|r| format!("{}: {} -> {}", r.address, r.before, r.after))
+			.collect();
+		assert_eq!(
+			rewritten,
+			[
+				"2: ADD #2, #3, [53] -> ADD #5, #0, [53]",
+				"6: JF [52], #28 -> JF [52], #34",
+				"9: MUL #4, #5, [54] -> ADD #20, #0, [54]",
+				"25: JT #1, #43 -> JT #1, #46",
+				"28: JT #1, #31 -> JT #1, #34",
+				"43: JF #0, #46 -> JT #1, #46",
+			]
+		);
+	}
+
+	#[test]
+	fn agrees_with_the_original_on_generated_programs() {
+		let mut rng = Rng::new(2019);
+		let mut optimized = 0;
+		for i in 0..30_000 {
+			let case =
+				generate(&mut rng, Features::ALL[i % Features::ALL.len()]);
+			if let Ok(result) = check(&case.program, &case.input) {
+				optimized += !result.rewrites.is_empty() as usize;
+			}
+		}
+		assert!(optimized > 0);
+	}
+}
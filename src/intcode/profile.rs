@@ -0,0 +1,120 @@
+use super::Instruction;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+/// Counts gathered while a machine runs with profiling turned on. Profiles of
+/// several machines running the same program can be merged.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+	/// Executions of the instruction at each address.
+	pub executions: BTreeMap<usize, u64>,
+	/// Executions of each instruction, by mnemonic.
+	pub opcodes: BTreeMap<&'static str, u64>,
+	/// Operand reads from each address.
+	pub reads: BTreeMap<usize, u64>,
+	/// Writes to each address.
+	pub writes: BTreeMap<usize, u64>,
+	/// Time spent waiting for input to become available.
+	pub input_wait: Duration,
+}
+
+fn merge_counts<K: Ord + Copy>(
+	into: &mut BTreeMap<K, u64>,
+	from: &BTreeMap<K, u64>,
+) {
+	for (&key, &count) in from {
+		*into.entry(key).or_insert(0) += count;
+	}
+}
+
+fn ranked<K: Copy>(counts: &BTreeMap<K, u64>) -> Vec<(K, u64)> {
+	let mut ranked: Vec<(K, u64)> =
+		counts.iter().map(|(&k, &c)| (k, c)).collect();
+	ranked.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+	ranked
+}
+
+impl Profile {
+	pub fn merge(&mut self, other: &Profile) {
+		merge_counts(&mut self.executions, &other.executions);
+		merge_counts(&mut self.opcodes, &other.opcodes);
+		merge_counts(&mut self.reads, &other.reads);
+		merge_counts(&mut self.writes, &other.writes);
+		self.input_wait += other.input_wait;
+	}
+
+	pub fn instructions(&self) -> u64 {
+		self.executions.values().sum()
+	}
+
+	/// Ranks the `top` most executed addresses, opcodes and most accessed
+	/// memory, disassembling each hot address against `memory`.
+	pub fn report(&self, memory: &[i64], top: usize) -> String {
+		let mut out = String::new();
+		self.write_report(&mut out, memory, top).unwrap();
+		out
+	}
+
+	fn write_report(
+		&self,
+		out: &mut impl Write,
+		memory: &[i64],
+		top: usize,
+	) -> fmt::Result {
+		let total = self.instructions().max(1) as f64;
+		let percent = |count: u64| 100.0 * count as f64 / total;
+		writeln!(out, "instructions executed: {}", self.instructions())?;
+		writeln!(out, "time waiting for input: {:?}", self.input_wait)?;
+
+		writeln!(out, "\nhot spots:")?;
+		writeln!(
+			out,
+			"{:>12} {:>7} {:>8}  instruction",
+			"count", "%", "address"
+		)?;
+		for (address, count) in ranked(&self.executions).into_iter().take(top) {
+			let instruction = match Instruction::decode(address, memory) {
+				Ok(instruction) => instruction.to_string(),
+				Err(_) => {
+					format!("DB {}", memory.get(address).copied().unwrap_or(0))
+				}
+			};
+			writeln!(
+				out,
+				"{:>12} {:>6.2}% {:>8}  {}",
+				count,
+				percent(count),
+				address,
+				instruction
+			)?;
+		}
+
+		writeln!(out, "\nopcodes:")?;
+		for (mnemonic, count) in ranked(&self.opcodes) {
+			writeln!(
+				out,
+				"{:>12} {:>6.2}%  {}",
+				count,
+				percent(count),
+				mnemonic
+			)?;
+		}
+
+		let mut accesses = BTreeMap::new();
+		merge_counts(&mut accesses, &self.reads);
+		merge_counts(&mut accesses, &self.writes);
+		writeln!(out, "\nmemory:")?;
+		writeln!(out, "{:>12} {:>12} {:>8}", "reads", "writes", "address")?;
+		for (address, _) in ranked(&accesses).into_iter().take(top) {
+			writeln!(
+				out,
+				"{:>12} {:>12} {:>8}",
+				self.reads.get(&address).copied().unwrap_or(0),
+				self.writes.get(&address).copied().unwrap_or(0),
+				address
+			)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,401 @@
+//! Recording the input a machine consumes and the output it produces, and
+//! replaying a recording to check that a run follows the same path.
+//!
+//! A recording is a line-oriented text file in the style of a snapshot. It
+//! starts with the state the machine was in when recording began, followed
+//! by one line per value with the step that consumed or produced it, and
+//! where the machine last stopped:
+//!
+//! ```text
+//! intcode-recording 2
+//! pointer 0
+//! relative_base 0
+//! steps 0
+//! memory_limit 16777216
+//! memory 3,9,8,9,10,9,4,9,99,-1,8
+//! input 1 8
+//! output 3 1
+//! stop 4 halted
+//! ```
+//!
+//! Version 1 recordings wrote the stop reason in Rust's debug format and left
+//! out the memory limit. They are rejected rather than guessed at; record the
+//! run again to get a version 2 file.
+//!
+//! Values read from mapped devices are not recorded, so a machine that uses
+//! them may not replay.
+
+use super::snapshot::{write_words, Error};
+use super::{io as vm_io, Fault, State, Vm, DEFAULT_MEMORY_LIMIT};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// What every recording starts with, followed by a space and the version.
+const MAGIC: &str = "intcode-recording";
+const HEADER: &str = "intcode-recording 2";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+	Input(i64),
+	Output(i64),
+}
+
+impl fmt::Display for Event {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Event::Input(value) => write!(f, "input {}", value),
+			Event::Output(value) => write!(f, "output {}", value),
+		}
+	}
+}
+
+/// Why the machine stopped, written as `halted`, `awaiting_input`,
+/// `budget_exhausted`, `infinite_loop` or `fault` followed by its message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+	Halted,
+	AwaitingInput,
+	BudgetExhausted,
+	InfiniteLoop,
+	Fault(String),
+}
+
+impl Stop {
+	pub(super) fn new(result: &Result<Option<State>, Fault>) -> Option<Self> {
+		Some(match result {
+			Ok(None) => return None,
+			Ok(Some(State::Halted)) => Stop::Halted,
+			Ok(Some(State::AwaitingInput)) => Stop::AwaitingInput,
+			Ok(Some(State::BudgetExhausted { .. })) => Stop::BudgetExhausted,
+			Ok(Some(State::InfiniteLoop { .. })) => Stop::InfiniteLoop,
+			Err(fault) => Stop::Fault(fault.to_string()),
+		})
+	}
+
+	fn parse(text: &str) -> Option<Self> {
+		Some(match text {
+			"halted" => Stop::Halted,
+			"awaiting_input" => Stop::AwaitingInput,
+			"budget_exhausted" => Stop::BudgetExhausted,
+			"infinite_loop" => Stop::InfiniteLoop,
+			_ => Stop::Fault(text.strip_prefix("fault ")?.to_owned()),
+		})
+	}
+}
+
+impl fmt::Display for Stop {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Stop::Halted => write!(f, "halted"),
+			Stop::AwaitingInput => write!(f, "awaiting_input"),
+			Stop::BudgetExhausted => write!(f, "budget_exhausted"),
+			Stop::InfiniteLoop => write!(f, "infinite_loop"),
+			Stop::Fault(message) => write!(f, "fault {}", message),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recording {
+	pub memory: Vec<i64>,
+	pub pointer: usize,
+	pub relative_base: i64,
+	pub steps: u64,
+	pub memory_limit: usize,
+	/// Each value consumed or produced, with the step that did so.
+	pub events: Vec<(u64, Event)>,
+	/// The step count when the machine last stopped, and why it did.
+	pub stop: Option<(u64, Stop)>,
+}
+
+impl Recording {
+	/// Starts a recording from the machine's current state.
+	pub(super) fn start(vm: &Vm) -> Self {
+		Recording {
+			memory: vm.memory.clone(),
+			pointer: vm.pointer,
+			relative_base: vm.relative_base,
+			steps: vm.steps,
+			memory_limit: vm.memory_limit,
+			events: Vec::new(),
+			stop: None,
+		}
+	}
+
+	pub fn inputs(&self) -> impl Iterator<Item = i64> + '_ {
+		self.events.iter().filter_map(|&(_, event)| match event {
+			Event::Input(value) => Some(value),
+			Event::Output(_) => None,
+		})
+	}
+
+	/// A machine in the state the recording started from, with every
+	/// recorded input queued, ready to run the same path again.
+	pub fn machine(&self) -> Vm {
+		let mut vm = Vm::new(self.memory.clone());
+		vm.pointer = self.pointer;
+		vm.relative_base = self.relative_base;
+		vm.steps = self.steps;
+		vm.memory_limit = self.memory_limit;
+		for value in self.inputs() {
+			vm.push_input(value);
+		}
+		vm
+	}
+}
+
+/// The first point where a replay left the recorded path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+	pub step: u64,
+	pub expected: String,
+	pub actual: String,
+}
+
+impl fmt::Display for Divergence {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"step {}: expected {}, got {}",
+			self.step, self.expected, self.actual
+		)
+	}
+}
+
+fn describe(event: Option<&(u64, Event)>) -> String {
+	match event {
+		Some((step, event)) => format!("{} at step {}", event, step),
+		None => "no more input or output".to_owned(),
+	}
+}
+
+fn describe_stop(stop: Option<&(u64, Stop)>) -> String {
+	match stop {
+		Some((step, why)) => format!("a stop at step {}: {}", step, why),
+		None => "no stop".to_owned(),
+	}
+}
+
+/// Runs the recorded machine again, feeding it the recorded input, and checks
+/// that every value is consumed or produced at the same step as before and
+/// that it stops in the same way. Returns the number of steps replayed.
+pub fn replay(recording: &Recording) -> Result<u64, Divergence> {
+	let mut vm = recording.machine();
+	let mut input = std::mem::take(&mut vm.input);
+	// Stop a run that has left the path one step after the recording did, so
+	// that one stuck in a loop still ends.
+	let end = match &recording.stop {
+		Some((step, Stop::BudgetExhausted)) => *step,
+		Some((step, stop)) => {
+			vm.detect_loops(*stop == Stop::InfiniteLoop);
+			step + 1
+		}
+		None => recording
+			.events
+			.last()
+			.map_or(recording.steps, |&(step, _)| step),
+	};
+	vm.set_budget(Some(end.saturating_sub(recording.steps)));
+	vm.set_recording(true);
+	let expected = &recording.events;
+	let mut checked = 0;
+	loop {
+		let result = vm.step_with(&mut input, vm_io::output_fn(|_| {}));
+		let actual = &vm.recording().unwrap().events;
+		for (i, event) in actual.iter().enumerate().skip(checked) {
+			if expected.get(i) != Some(event) {
+				return Err(Divergence {
+					step: event.0,
+					expected: describe(expected.get(i)),
+					actual: describe(Some(event)),
+				});
+			}
+		}
+		checked = actual.len();
+		if !matches!(result, Ok(None)) {
+			break;
+		}
+	}
+	let stop = vm.take_recording().unwrap().stop;
+	if let Some(missing) = expected.get(checked) {
+		return Err(Divergence {
+			step: vm.steps(),
+			expected: describe(Some(missing)),
+			actual: describe_stop(stop.as_ref()),
+		});
+	}
+	match &recording.stop {
+		Some(expected) if stop.as_ref() != Some(expected) => Err(Divergence {
+			step: vm.steps(),
+			expected: describe_stop(Some(expected)),
+			actual: describe_stop(stop.as_ref()),
+		}),
+		_ => Ok(vm.steps() - recording.steps),
+	}
+}
+
+pub fn write(recording: &Recording, mut out: impl Write) -> io::Result<()> {
+	writeln!(out, "{}", HEADER)?;
+	writeln!(out, "pointer {}", recording.pointer)?;
+	writeln!(out, "relative_base {}", recording.relative_base)?;
+	writeln!(out, "steps {}", recording.steps)?;
+	writeln!(out, "memory_limit {}", recording.memory_limit)?;
+	write_words(&mut out, "memory", &recording.memory)?;
+	for (step, event) in &recording.events {
+		match event {
+			Event::Input(value) => writeln!(out, "input {} {}", step, value)?,
+			Event::Output(value) => writeln!(out, "output {} {}", step, value)?,
+		}
+	}
+	if let Some((step, why)) = &recording.stop {
+		writeln!(out, "stop {} {}", step, why)?;
+	}
+	out.flush()
+}
+
+pub fn read(input: impl BufRead) -> Result<Recording, Error> {
+	let mut recording = Recording {
+		memory: Vec::new(),
+		pointer: 0,
+		relative_base: 0,
+		steps: 0,
+		memory_limit: DEFAULT_MEMORY_LIMIT,
+		events: Vec::new(),
+		stop: None,
+	};
+	let mut seen_header = false;
+	for (i, line) in input.lines().enumerate() {
+		let line = line?;
+		let error = |message: String| Error::Format {
+			line: i + 1,
+			message,
+		};
+		if !seen_header {
+			let line = line.trim();
+			if line != HEADER {
+				return Err(error(match line.strip_prefix(MAGIC) {
+					Some(version) if version.starts_with(' ') => format!(
+						"unsupported recording version{}, expected {}",
+						version,
+						&HEADER[MAGIC.len() + 1..]
+					),
+					_ => "not an Intcode recording".to_owned(),
+				}));
+			}
+			seen_header = true;
+			continue;
+		}
+		let line = line.trim();
+		if line.is_empty() {
+			continue;
+		}
+		let mut fields = line.splitn(3, ' ');
+		let key = fields.next().unwrap_or("");
+		let first = fields.next().unwrap_or("");
+		let rest = fields.next().unwrap_or("");
+		let number = |text: &str| {
+			text.parse::<i64>()
+				.map_err(|_| error(format!("invalid number '{}'", text)))
+		};
+		let step = || {
+			first
+				.parse::<u64>()
+				.map_err(|_| error(format!("invalid step '{}'", first)))
+		};
+		match key {
+			"pointer" => recording.pointer = step()? as usize,
+			"relative_base" => recording.relative_base = number(first)?,
+			"steps" => recording.steps = step()?,
+			"memory_limit" => recording.memory_limit = step()? as usize,
+			"memory" if first.is_empty() => recording.memory = Vec::new(),
+			"memory" => {
+				recording.memory = first
+					.split(',')
+					.map(|w| number(w.trim()))
+					.collect::<Result<_, _>>()?
+			}
+			"input" => recording
+				.events
+				.push((step()?, Event::Input(number(rest)?))),
+			"output" => recording
+				.events
+				.push((step()?, Event::Output(number(rest)?))),
+			"stop" => {
+				let stop = Stop::parse(rest)
+					.ok_or_else(|| error(format!("invalid stop '{}'", rest)))?;
+				recording.stop = Some((step()?, stop))
+			}
+			_ => return Err(error(format!("unknown field '{}'", key))),
+		}
+	}
+	if !seen_header {
+		return Err(Error::Format {
+			line: 1,
+			message: "empty recording".to_owned(),
+		});
+	}
+	Ok(recording)
+}
+
+pub fn save(recording: &Recording, path: impl AsRef<Path>) -> io::Result<()> {
+	write(recording, BufWriter::new(File::create(path)?))
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Recording, Error> {
+	read(BufReader::new(File::open(path)?))
+}
+
+/// Whether `contents` looks like a recording rather than a plain program.
+pub fn is_recording(contents: &str) -> bool {
+	contents.trim_start().starts_with(MAGIC)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip_keeps_the_memory_limit_and_stop() {
+		// Reads a value and stores it past a 16-word limit.
+		let mut vm = Vm::new(vec![3, 7, 1001, 7, 0, 100, 99, 0]);
+		vm.set_memory_limit(16);
+		vm.set_recording(true);
+		vm.push_input(4);
+		assert!(vm.run().is_err());
+		let recording = vm.take_recording().unwrap();
+		assert!(matches!(recording.stop, Some((1, Stop::Fault(_)))));
+
+		let mut text = Vec::new();
+		write(&recording, &mut text).unwrap();
+		let read_back = read(&text[..]).unwrap();
+		assert_eq!(read_back, recording);
+		assert_eq!(replay(&read_back), Ok(1));
+	}
+
+	#[test]
+	fn reads_the_example_and_rejects_version_1() {
+		let example = "intcode-recording 2
+			pointer 0
+			relative_base 0
+			steps 0
+			memory_limit 16777216
+			memory 3,9,8,9,10,9,4,9,99,-1,8
+			input 1 8
+			output 3 1
+			stop 4 halted";
+		let recording = read(example.as_bytes()).unwrap();
+		assert_eq!(recording.stop, Some((4, Stop::Halted)));
+		assert_eq!(replay(&recording), Ok(4));
+
+		let old = example.replacen("recording 2", "recording 1", 1);
+		assert!(is_recording(&old));
+		let error = read(old.as_bytes()).unwrap_err().to_string();
+		assert!(
+			error.contains("unsupported recording version 1"),
+			"{}",
+			error
+		);
+	}
+}
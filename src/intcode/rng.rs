@@ -0,0 +1,35 @@
+//! A small pseudo-random number generator shared by the fuzzer and the
+//! random-number device.
+
+/// A xorshift generator, so that a run can be repeated from its seed.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+	pub fn new(seed: u64) -> Self {
+		// Xorshift gets stuck at zero, so mix the seed into a nonzero state.
+		Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	/// A number in `0..n`.
+	pub fn below(&mut self, n: usize) -> usize {
+		(self.next_u64() % n as u64) as usize
+	}
+
+	/// A number in `low..=high`.
+	pub fn between(&mut self, low: i64, high: i64) -> i64 {
+		low + (self.next_u64() % (high - low + 1) as u64) as i64
+	}
+
+	/// True one time in `n`.
+	pub fn one_in(&mut self, n: usize) -> bool {
+		self.below(n) == 0
+	}
+}
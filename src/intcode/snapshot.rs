@@ -0,0 +1,152 @@
+//! Saving a paused machine to a file and loading it back.
+//!
+//! A snapshot is a small line-oriented text file:
+//!
+//! ```text
+//! intcode-snapshot 1
+//! pointer 12
+//! relative_base 0
+//! steps 345
+//! memory_limit 16777216
+//! memory 3,21,1008,21,8,20,...
+//! input 5,6
+//! output 1000
+//! ```
+
+use super::Vm;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+const HEADER: &str = "intcode-snapshot 1";
+
+#[derive(Debug)]
+pub enum Error {
+	Io(io::Error),
+	Format { line: usize, message: String },
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Io(e) => write!(f, "{}", e),
+			Error::Format { line, message } => {
+				write!(f, "line {}: {}", line, message)
+			}
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Error::Io(e)
+	}
+}
+
+pub(super) fn write_words<'a>(
+	mut out: impl Write,
+	key: &str,
+	words: impl IntoIterator<Item = &'a i64>,
+) -> io::Result<()> {
+	write!(out, "{}", key)?;
+	for (i, word) in words.into_iter().enumerate() {
+		write!(out, "{}{}", if i == 0 { ' ' } else { ',' }, word)?;
+	}
+	writeln!(out)
+}
+
+pub fn write(vm: &Vm, mut out: impl Write) -> io::Result<()> {
+	writeln!(out, "{}", HEADER)?;
+	writeln!(out, "pointer {}", vm.pointer)?;
+	writeln!(out, "relative_base {}", vm.relative_base)?;
+	writeln!(out, "steps {}", vm.steps)?;
+	writeln!(out, "memory_limit {}", vm.memory_limit)?;
+	write_words(&mut out, "memory", &vm.memory)?;
+	write_words(&mut out, "input", &vm.input)?;
+	write_words(&mut out, "output", &vm.output)?;
+	out.flush()
+}
+
+pub fn read(input: impl BufRead) -> Result<Vm, Error> {
+	let mut vm = Vm::new(Vec::new());
+	let mut seen_header = false;
+	for (i, line) in input.lines().enumerate() {
+		let line = line?;
+		let number = i + 1;
+		let error = |message: String| Error::Format {
+			line: number,
+			message,
+		};
+		if !seen_header {
+			if line.trim() != HEADER {
+				return Err(error("not an Intcode snapshot".to_owned()));
+			}
+			seen_header = true;
+			continue;
+		}
+		let line = line.trim();
+		if line.is_empty() {
+			continue;
+		}
+		let (key, value) = match line.find(' ') {
+			Some(i) => (&line[..i], line[i + 1..].trim()),
+			None => (line, ""),
+		};
+		let words = || -> Result<Vec<i64>, Error> {
+			if value.is_empty() {
+				return Ok(Vec::new());
+			}
+			value
+				.split(',')
+				.map(|w| {
+					w.trim()
+						.parse()
+						.map_err(|_| error(format!("invalid word '{}'", w)))
+				})
+				.collect()
+		};
+		let number = || {
+			value
+				.parse::<u64>()
+				.map_err(|_| error(format!("invalid {} '{}'", key, value)))
+		};
+		match key {
+			"pointer" => vm.pointer = number()? as usize,
+			"relative_base" => {
+				vm.relative_base = value.parse().map_err(|_| {
+					error(format!("invalid relative_base '{}'", value))
+				})?
+			}
+			"steps" => vm.steps = number()?,
+			"memory_limit" => vm.memory_limit = number()? as usize,
+			"memory" => vm.memory = words()?,
+			"input" => vm.input = words()?.into_iter().collect::<VecDeque<_>>(),
+			"output" => vm.output = words()?,
+			_ => return Err(error(format!("unknown field '{}'", key))),
+		}
+	}
+	if !seen_header {
+		return Err(Error::Format {
+			line: 1,
+			message: "empty snapshot".to_owned(),
+		});
+	}
+	Ok(vm)
+}
+
+pub fn save(vm: &Vm, path: impl AsRef<Path>) -> io::Result<()> {
+	write(vm, BufWriter::new(File::create(path)?))
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Vm, Error> {
+	read(BufReader::new(File::open(path)?))
+}
+
+/// Whether `contents` looks like a snapshot rather than a plain program.
+pub fn is_snapshot(contents: &str) -> bool {
+	contents.trim_start().starts_with(HEADER)
+}
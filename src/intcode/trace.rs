@@ -0,0 +1,205 @@
+use super::{Fault, Instruction, State, Vm};
+use std::fmt;
+use std::io::{self, Write};
+
+#[derive(Debug)]
+pub enum Error {
+	Io(io::Error),
+	Fault(Fault),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Io(e) => write!(f, "{}", e),
+			Error::Fault(fault) => write!(f, "{}", fault),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Error::Io(e)
+	}
+}
+
+impl From<Fault> for Error {
+	fn from(fault: Fault) -> Self {
+		Error::Fault(fault)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+	Text,
+	JsonLines,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryWrite {
+	pub address: usize,
+	pub old: i64,
+	pub new: i64,
+}
+
+/// One executed instruction. Operands are resolved: parameters that are read
+/// hold the value read, and the destination holds the address written. An
+/// operand the instruction did not need, such as the target of a jump not
+/// taken, is `None` if it could not have been resolved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+	pub step: u64,
+	pub pointer: usize,
+	pub instruction: Instruction,
+	pub operands: Vec<Option<i64>>,
+	pub write: Option<MemoryWrite>,
+}
+
+impl Record {
+	pub fn write_text(&self, mut out: impl Write) -> io::Result<()> {
+		let operands: Vec<String> = self
+			.operands
+			.iter()
+			.map(|o| o.map_or("?".to_owned(), |o| o.to_string()))
+			.collect();
+		write!(
+			out,
+			"{:>8} {:>6}  {:<28} ({})",
+			self.step,
+			self.pointer,
+			self.instruction.to_string(),
+			operands.join(", ")
+		)?;
+		if let Some(w) = self.write {
+			write!(out, "  [{}] {} -> {}", w.address, w.old, w.new)?;
+		}
+		writeln!(out)
+	}
+
+	pub fn write_json(&self, mut out: impl Write) -> io::Result<()> {
+		let operands: Vec<String> = self
+			.operands
+			.iter()
+			.map(|o| o.map_or("null".to_owned(), |o| o.to_string()))
+			.collect();
+		write!(
+			out,
+			"{{\"step\":{},\"pointer\":{},\"mnemonic\":\"{}\",\
+			 \"instruction\":\"{}\",\"operands\":[{}],\"write\":",
+			self.step,
+			self.pointer,
+			self.instruction.mnemonic(),
+			self.instruction,
+			operands.join(",")
+		)?;
+		match self.write {
+			Some(w) => write!(
+				out,
+				"{{\"address\":{},\"old\":{},\"new\":{}}}",
+				w.address, w.old, w.new
+			)?,
+			None => write!(out, "null")?,
+		}
+		writeln!(out, "}}")
+	}
+}
+
+/// Drives a machine one instruction at a time, writing a record of every
+/// instruction it executes.
+pub struct Tracer<W: Write> {
+	out: W,
+	format: Format,
+}
+
+impl<W: Write> Tracer<W> {
+	pub fn new(out: W, format: Format) -> Self {
+		Tracer { out, format }
+	}
+
+	pub fn into_inner(self) -> W {
+		self.out
+	}
+
+	/// Executes one instruction. A fault is reported before anything is
+	/// written for the instruction that caused it.
+	pub fn step(&mut self, vm: &mut Vm) -> Result<Option<State>, Error> {
+		// A spent budget stops the machine before it decodes anything.
+		if vm.budget() == Some(0) {
+			return Ok(vm.step()?);
+		}
+		let pointer = vm.pointer();
+		let step = vm.steps() + 1;
+		let instruction = Instruction::decode(pointer, vm.memory())?;
+		let parameters = instruction.parameters();
+		// Operands are resolved before the machine runs the instruction, so
+		// one it never uses must not turn into a fault here.
+		let destination =
+			instruction.destination().and_then(|d| vm.address(d).ok());
+		let mut operands = Vec::with_capacity(parameters.len());
+		for (i, &p) in parameters.iter().enumerate() {
+			operands.push(match destination {
+				Some(address) if i == parameters.len() - 1 => {
+					Some(address as i64)
+				}
+				_ => vm.read(p).ok(),
+			});
+		}
+		let old = destination.map(|address| vm.peek(address));
+
+		let state = vm.step()?;
+		// The machine stopped without running the instruction.
+		if state == Some(State::AwaitingInput) {
+			return Ok(state);
+		}
+
+		let record = Record {
+			step,
+			pointer,
+			instruction,
+			operands,
+			write: destination.zip(old).map(|(address, old)| MemoryWrite {
+				address,
+				old,
+				new: vm.peek(address),
+			}),
+		};
+		match self.format {
+			Format::Text => record.write_text(&mut self.out)?,
+			Format::JsonLines => record.write_json(&mut self.out)?,
+		}
+		Ok(state)
+	}
+
+	pub fn run(&mut self, vm: &mut Vm) -> Result<State, Error> {
+		loop {
+			if let Some(state) = self.step(vm)? {
+				self.out.flush()?;
+				return Ok(state);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn spent_budget_stops_before_an_undecodable_word() {
+		let run = |traced: bool| {
+			let mut vm = Vm::new(vec![1105, 1, 3, 0]);
+			vm.set_budget(Some(1));
+			if traced {
+				Tracer::new(Vec::new(), Format::Text)
+					.run(&mut vm)
+					.map_err(|e| e.to_string())
+			} else {
+				vm.run().map_err(|e| e.to_string())
+			}
+		};
+		assert_eq!(run(false), Ok(State::BudgetExhausted { pointer: 3 }));
+		assert_eq!(run(true), run(false));
+	}
+}
@@ -1,3 +1,4 @@
 pub mod days;
+pub mod intcode;
 
 aoc_runner_derive::aoc_lib! { year = 2019 }